@@ -1,13 +1,22 @@
 use std::process::{Command, Stdio, exit};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::collections::HashMap;
+use std::cell::OnceCell;
 use std::env;
-
-use clap::{Arg, App, AppSettings, SubCommand};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::{Arg, App, AppSettings, Shell, SubCommand};
 use colored::*;
 use quick_error::quick_error;
 use users::{get_user_by_uid, get_current_uid, get_current_gid};
 use semver::Version;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
 
 quick_error! {
     #[derive(Debug)]
@@ -34,7 +43,10 @@ quick_error! {
             display("Unsupported parameter: {}", descr)
         }
         NoConfigFound { command: String } {
-            display("No docker image found for '{}' in .contain.yaml or any path above", command)
+            display("No docker image found for '{}' in any of {} in this or any parent directory", command, config_file_names())
+        }
+        NoConfigFileFound {
+            display("None of {} found in this or any parent directory", config_file_names())
         }
         ImageBuildFailed { image: String, dockerfile: String } {
             display("Unable to build docker image '{}' from dockerfile '{}'", image, dockerfile)
@@ -45,6 +57,30 @@ quick_error! {
         ContainerAlreadyRunning { name: String } {
             display("Container '{}' is already running. Use 'contain down' first, or use 'contain run' to execute commands inside it.", name)
         }
+        FileExists { path: String } {
+            display("'{}' already exists, use --force to overwrite it", path)
+        }
+        ConfigShadowed { existing: String, created: String } {
+            display("'{}' already configures contain here and '{}' would take precedence over it, use --force to create it anyway", existing, created)
+        }
+        ContainerNotFound { name: String } {
+            display("Container '{}' does not exist. Use 'contain up' to create and start it.", name)
+        }
+        ContainerNotRunning { name: String } {
+            display("Container '{}' is not running. Use 'contain up' to start it.", name)
+        }
+        ContainerRestarting { name: String } {
+            display("Container '{}' is restarting because its main process keeps exiting. Use 'contain logs' to see why, or 'contain down' to remove it.", name)
+        }
+        ContainerStateUnknown { name: String } {
+            display("Container '{}' is in a state contain does not know. Use 'contain status' to inspect it, or 'contain down' to remove it.", name)
+        }
+        WorkspaceMismatch { name: String, mounted: String, root: String } {
+            display("Container '{}' mounts '{}' rather than '{}'. Use 'contain down' in that checkout, or set a different 'name:'", name, mounted, root)
+        }
+        WorkdirMismatch { name: String, mounted_at: String, workdir: String } {
+            display("Container '{}' mounts this project at '{}' rather than '{}'. Run without --workdir, or use 'contain up --recreate --workdir {}' to move it", name, mounted_at, workdir, workdir)
+        }
         ContainerStopFailed { name: String, reason: String } {
             display("Failed to stop container '{}': {}", name, reason)
         }
@@ -55,7 +91,110 @@ quick_error! {
 }
 
 const CONTAIN_FILENAME: &str = ".contain.yaml";
+// Looked up in this order in each directory
+const CONFIG_FILENAMES: &[&str] = &[
+    ".contain.yaml", ".contain.yml", ".contain.toml", ".contain.json",
+    "contain.yaml", "contain.yml", "contain.toml", "contain.json",
+];
+// Project manifests that may embed the configuration, after CONFIG_FILENAMES in priority
+const EMBEDDED_CONFIG_SOURCES: &[(&str, &[&str])] = &[
+    ("Cargo.toml", &["package.metadata.contain", "workspace.metadata.contain"]),
+    ("package.json", &["contain"]),
+];
+const USER_CONFIG_FILENAME: &str = "config.yaml";
 const DEFAULT_SHELL: &str = "/bin/bash";
+const RESTART_POLICIES: &[&str] = &["no", "always", "unless-stopped", "on-failure"];
+const CONFIG_HASH_LABEL: &str = "contain.config-hash";
+const IDLE_TIMEOUT_LABEL: &str = "contain.idle-timeout";
+const ROOT_PATH_LABEL: &str = "contain.root-path";
+const MANAGED_LABEL: &str = "contain.managed";
+const VERSION_LABEL: &str = "contain.version";
+const CONFIG_FILE_LABEL: &str = "contain.config-file";
+const ENTRY_LABEL: &str = "contain.entry";
+const CREATED_AT_LABEL: &str = "contain.created-at";
+// Minimum number of seconds between two automatic idle container sweeps
+const REAP_INTERVAL_SECS: u64 = 300;
+// Project-local shim directory, relative to the directory holding .contain.yaml
+const SHIMS_DIR: &str = ".contain/bin";
+// Marker line identifying files generated by 'contain shims'
+const SHIM_MARKER: &str = "# contain shim";
+// Dockerfile written by 'contain init', kept apart from any production Dockerfile
+const INIT_DOCKERFILE: &str = "Dockerfile.contain";
+const BASE_IMAGE_ID_LABEL: &str = "contain.base-image-id";
+const LOCK_FILENAME: &str = ".contain.lock";
+const IMAGE_LABEL: &str = "contain.image";
+const BUILD_HASH_LABEL: &str = "contain.build-hash";
+const BUILT_AT_LABEL: &str = "contain.built-at";
+// Where the docker CLI connects when neither DOCKER_HOST nor a context says otherwise
+const DEFAULT_DOCKER_HOST: &str = "unix:///var/run/docker.sock";
+
+/// Scaffolding used by 'contain init' for one ecosystem.
+struct InitTemplate {
+    name: &'static str,
+    // Files whose presence identifies the ecosystem
+    markers: &'static [&'static str],
+    base_image: &'static str,
+    commands: &'static [&'static str],
+    ports: &'static [&'static str],
+    // Environment pointing the ecosystem's caches into the /cache volume
+    env: &'static [&'static str],
+}
+
+// Detection order matters: the first template with a marker file present wins
+const INIT_TEMPLATES: &[InitTemplate] = &[
+    InitTemplate {
+        name: "rust",
+        markers: &["Cargo.toml"],
+        base_image: "rust:1-bookworm",
+        commands: &["cargo", "rustc", "rustup"],
+        ports: &[],
+        // CARGO_HOME moves off the image's /usr/local/cargo, keep its toolchain binaries on PATH
+        env: &[
+            "CARGO_HOME=/cache/cargo",
+            "PATH=/cache/cargo/bin:/usr/local/cargo/bin:/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin",
+        ],
+    },
+    InitTemplate {
+        name: "node",
+        markers: &["package.json"],
+        base_image: "node:lts-bookworm",
+        commands: &["node", "npm", "npx", "yarn"],
+        ports: &["3000:3000"],
+        env: &["NPM_CONFIG_CACHE=/cache/npm", "YARN_CACHE_FOLDER=/cache/yarn"],
+    },
+    InitTemplate {
+        name: "go",
+        markers: &["go.mod"],
+        base_image: "golang:1-bookworm",
+        commands: &["go", "gofmt"],
+        ports: &["8080:8080"],
+        env: &["GOCACHE=/cache/go-build", "GOMODCACHE=/cache/go-mod"],
+    },
+    InitTemplate {
+        name: "java",
+        markers: &["pom.xml"],
+        base_image: "maven:3-eclipse-temurin-21",
+        commands: &["mvn", "java", "javac"],
+        ports: &["8080:8080"],
+        env: &["MAVEN_OPTS=-Dmaven.repo.local=/cache/m2"],
+    },
+    InitTemplate {
+        name: "gradle",
+        markers: &["build.gradle", "build.gradle.kts", "gradlew"],
+        base_image: "gradle:jdk21",
+        commands: &["gradle", "./gradlew", "java", "javac"],
+        ports: &["8080:8080"],
+        env: &["GRADLE_USER_HOME=/cache/gradle"],
+    },
+    InitTemplate {
+        name: "python",
+        markers: &["pyproject.toml", "requirements.txt", "setup.py", "Pipfile"],
+        base_image: "python:3-bookworm",
+        commands: &["python", "python3", "pip", "pytest"],
+        ports: &["8000:8000"],
+        env: &["PIP_CACHE_DIR=/cache/pip"],
+    },
+];
 
 #[derive(Debug)]
 struct GlobalOptions {
@@ -65,7 +204,22 @@ struct GlobalOptions {
     dry_run: bool,
     skip_ports: bool,
     skip_name: bool,
-    cli_env_variables: Vec<String>
+    cli_env_variables: Vec<String>,
+    overrides: ConfigOverrides,
+}
+
+/// Per-invocation replacements for what the configuration files say, from the global flags.
+#[derive(Debug, Default)]
+struct ConfigOverrides {
+    image: Option<String>,
+    entry: Option<String>,
+    workdir: Option<String>,
+    // Absolute path of the file given with --config or CONTAIN_CONFIG, which disables discovery
+    config: Option<PathBuf>,
+    // From --profile or CONTAIN_PROFILE, `none` or empty disables profiles
+    profile: Option<String>,
+    // Leave `var:` commands unevaluated, for displaying the configuration
+    skip_var_commands: bool,
 }
 
 impl GlobalOptions {
@@ -138,8 +292,21 @@ fn passthrough_command(command: &str, args: Vec<&str>, options: &GlobalOptions)
 struct Configuration {
     image: String,
     name: Option<String>,
-    dockerfile: String,
+    dockerfile: Option<String>,
+    dockerfile_inline: Option<String>,
+    build_context: Option<String>,
+    build_target: Option<String>,
+    cache_from: Vec<String>,
+    build_secrets: Vec<String>,
+    buildx: bool,
+    buildx_builder: Option<String>,
+    platform: Option<String>,
+    build_ssh: Vec<String>,
+    cache_to: Vec<String>,
+    cache_dir: Option<String>,
+    build_progress: Option<String>,
     root_path: PathBuf,
+    config_file: PathBuf,
     flags: Vec<String>,
     workdir_path: String,
     env_variables: Vec<String>,
@@ -147,6 +314,98 @@ struct Configuration {
     extra_mounts: Vec<String>,
     ports: Vec<String>,
     default_shell: Option<String>,
+    up_command: Option<Vec<String>>,
+    restart: Option<String>,
+    idle_timeout: Option<u64>,
+    autostart: bool,
+    user_layer: bool,
+    user_layer_sudo: bool,
+    tag_content_hash: bool,
+    registry: Option<String>,
+    push_tags: Vec<String>,
+    pinned: Option<String>,
+    image_overridden: bool,
+    profile: Option<String>,
+    entry: String,
+}
+
+/// Splits an image reference into repository and tag. The tag is the part after the last ':'
+/// following the last '/', `latest` if absent, or the first 12 hex digits of a digest.
+fn split_image_reference(image: &str) -> (String, String) {
+    match image.split_once('@') {
+        Some((repository, digest)) => {
+            let digest = digest.rsplit(':').next().unwrap_or(digest);
+            (repository.to_string(), digest.chars().take(12).collect::<String>())
+        }
+        None => match image.rsplit_once(':') {
+            Some((repository, tag)) if !tag.contains('/') => (repository.to_string(), tag.to_string()),
+            _ => (image.to_string(), "latest".to_string()),
+        },
+    }
+}
+
+impl Configuration {
+    /// Repository the image is pushed to and pulled from, prefixed with `registry:` if set.
+    fn remote_repository(&self) -> String {
+        let (repository, _) = split_image_reference(&self.image);
+        match &self.registry {
+            Some(registry) => format!("{}/{}", registry, repository),
+            None => repository,
+        }
+    }
+
+    fn remote_image(&self) -> String {
+        match &self.registry {
+            Some(registry) => format!("{}/{}", registry, self.image),
+            None => self.image.clone(),
+        }
+    }
+
+    /// Whether `contain push` has somewhere to publish to, so a pushed content-hash tag may exist.
+    fn has_push_target(&self) -> bool {
+        self.registry.is_some() || !self.push_tags.is_empty()
+    }
+
+    /// Tag under which `contain push` publishes a build of the given build hash.
+    fn content_hash_image(&self, hash: &str) -> String {
+        format!("{}:contain-{}", self.remote_repository(), &hash[..12])
+    }
+
+    /// Human readable origin of the Dockerfile, used in build output and errors.
+    fn dockerfile_description(&self) -> String {
+        match &self.dockerfile {
+            Some(dockerfile) => format!("{}/{}", self.root_path.display(), dockerfile),
+            None => "<dockerfile_inline>".to_string(),
+        }
+    }
+
+    /// The configured image, or the digest it is pinned to in `.contain.lock`.
+    fn base_image(&self) -> String {
+        self.pinned.clone().unwrap_or_else(|| self.image.clone())
+    }
+
+    /// What the user layer is built FROM: the pinned registry digest, or else the configured
+    /// image. BuildKit resolves FROM by reference, so a lock pinning a bare local image id
+    /// cannot be used there.
+    fn user_layer_base(&self) -> String {
+        match &self.pinned {
+            Some(pinned) if pinned.contains('@') => pinned.clone(),
+            _ => self.image.clone(),
+        }
+    }
+
+    /// The image containers are created from: the (pinned) configured image, or the derived
+    /// per-user image when `user_layer: auto` is set.
+    fn run_image(&self) -> String {
+        if !self.user_layer {
+            return self.base_image();
+        }
+
+        let (repository, tag) = split_image_reference(&self.image);
+
+        format!("{}:{}-contain-{}-{}{}", repository, tag, get_current_uid(), get_current_gid(),
+            if self.user_layer_sudo { "-sudo" } else { "" })
+    }
 }
 
 fn get_required_string(table: &HashMap<String, config::Value>, field: &str, file: &str) -> Result<String, Error> {
@@ -178,7 +437,21 @@ fn get_optional_string(table: &HashMap<String, config::Value>, field: &str, file
     }
 }
 
-fn get_string_array(table: &HashMap<String, config::Value>, field: &str, file: &str) -> Result<Vec<String>, Error> {
+fn get_optional_bool(table: &HashMap<String, config::Value>, field: &str, file: &str) -> Result<Option<bool>, Error> {
+    match table.get(field) {
+        None => Ok(None),
+        Some(v) => v.clone()
+            .into_bool()
+            .map(Some)
+            .map_err(|_| Error::ConfigInvalidValue {
+                file: file.to_string(),
+                field: field.to_string(),
+                reason: "expected true or false".to_string()
+            })
+    }
+}
+
+fn get_string_array(table: &HashMap<String, config::Value>, field: &str, file: &str, templates: &TemplateContext) -> Result<Vec<String>, Error> {
     match table.get(field) {
         None => Ok(Vec::new()),
         Some(node) => {
@@ -196,19 +469,175 @@ fn get_string_array(table: &HashMap<String, config::Value>, field: &str, file: &
                         field: field.to_string(),
                         reason: "expected array of strings".to_string()
                     })?;
-                    shellexpand::env(&s)
-                        .map(|expanded| expanded.into_owned())
-                        .map_err(|e| Error::ConfigInvalidValue {
-                            file: file.to_string(),
-                            field: field.to_string(),
-                            reason: format!("environment variable expansion failed: {}", e)
-                        })
+                    expand_template(&s, file, field, templates)
                 })
                 .collect()
         }
     }
 }
 
+/// The project a configuration entry is parsed for, which built-in template variables refer to.
+struct TemplateContext {
+    root_path: PathBuf,
+    workdir: String,
+    // Stand-ins for `var:` entries whose commands were not run
+    unevaluated: HashMap<String, String>,
+    // git is asked at most once per entry, however often the variables are used
+    git_branch: OnceCell<Option<String>>,
+    git_sha: OnceCell<Option<String>>,
+}
+
+impl TemplateContext {
+    fn new(root_path: &Path, overrides: &ConfigOverrides) -> TemplateContext {
+        TemplateContext {
+            root_path: root_path.to_path_buf(),
+            workdir: overrides.workdir.clone().unwrap_or_else(|| "/workdir".to_owned()),
+            unevaluated: HashMap::new(),
+            git_branch: OnceCell::new(),
+            git_sha: OnceCell::new(),
+        }
+    }
+}
+
+/// Built-in template variables, resolved for the project in `templates`. Lowercase so
+/// they cannot be confused with environment variables, which are used for all other names.
+fn template_variable(name: &str, templates: &TemplateContext) -> Option<String> {
+    let git = |args: &[&str]| -> Option<String> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&templates.root_path)
+            .args(args)
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string()).filter(|s| !s.is_empty())
+    };
+
+    match name {
+        "project_name" => Some(project_slug(&templates.root_path)),
+        // Usable in names and tags, so feature/x becomes feature-x. A detached HEAD has no
+        // branch, which leaves the variable unset so that a `:-default` applies.
+        "git_branch" => templates.git_branch.get_or_init(|| git(&["rev-parse", "--abbrev-ref", "HEAD"])
+            .filter(|branch| branch != "HEAD")
+            .map(|branch| branch.replace(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-'), "-")
+                .trim_start_matches(['.', '-'])
+                .to_string())
+            .filter(|branch| !branch.is_empty())).clone(),
+        "git_sha" => templates.git_sha.get_or_init(|| git(&["rev-parse", "--short", "HEAD"])).clone(),
+        "uid" => Some(get_current_uid().to_string()),
+        "gid" => Some(get_current_gid().to_string()),
+        "username" => Some(host_username()),
+        "config_dir" => Some(templates.root_path.to_string_lossy().into_owned()),
+        "workdir" => Some(templates.workdir.clone()),
+        "CONTAIN_ROOT_PATH" => Some(templates.root_path.to_string_lossy().into_owned()),
+        _ => templates.unevaluated.get(name).cloned().or_else(|| env::var(name).ok()),
+    }
+}
+
+/// Expands `$VAR`, `${VAR}` and `${VAR:-default}` using the built-in template variables and
+/// the environment. A variable that is undefined (or empty, with a default) and has no
+/// default is an error rather than being left in place.
+fn expand_template(input: &str, file: &str, field: &str, templates: &TemplateContext) -> Result<String, Error> {
+    shellexpand::env_with_context(input, |var: &str| {
+        let (name, default) = match var.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (var, None),
+        };
+
+        match (template_variable(name, templates), default) {
+            (Some(value), Some(default)) if value.is_empty() => Ok(Some(default.to_string())),
+            (Some(value), _) => Ok(Some(value)),
+            (None, Some(default)) => Ok(Some(default.to_string())),
+            (None, None) => Err(()),
+        }
+    })
+    .map(|expanded| expanded.into_owned())
+    .map_err(|e| Error::ConfigInvalidValue {
+        file: file.to_string(),
+        field: field.to_string(),
+        reason: format!("undefined variable '{}'", e.var_name)
+    })
+}
+
+/// Reads a command that may be given either as a single string (run through `sh -c`)
+/// or as an argv list that is passed to docker verbatim.
+fn get_optional_command(table: &HashMap<String, config::Value>, field: &str, file: &str, templates: &TemplateContext) -> Result<Option<Vec<String>>, Error> {
+    let node = match table.get(field) {
+        None => return Ok(None),
+        Some(node) => node,
+    };
+
+    if let Ok(string) = node.clone().into_string() {
+        return Ok(Some(vec!["sh".to_string(), "-c".to_string(), string]));
+    }
+
+    let argv = get_string_array(table, field, file, templates)?;
+    if argv.is_empty() {
+        return Err(Error::ConfigInvalidValue {
+            file: file.to_string(),
+            field: field.to_string(),
+            reason: "expected a non-empty command".to_string()
+        });
+    }
+
+    Ok(Some(argv))
+}
+
+fn get_restart_policy(table: &HashMap<String, config::Value>, file: &str) -> Result<Option<String>, Error> {
+    let policy = match get_optional_string(table, "restart", file)? {
+        None => return Ok(None),
+        Some(p) => p,
+    };
+
+    let (base, retries) = match policy.split_once(':') {
+        Some((base, retries)) => (base, Some(retries)),
+        None => (policy.as_str(), None),
+    };
+
+    let valid = RESTART_POLICIES.contains(&base) && match retries {
+        None => true,
+        Some(n) => base == "on-failure" && n.parse::<u32>().is_ok(),
+    };
+
+    if !valid {
+        return Err(Error::ConfigInvalidValue {
+            file: file.to_string(),
+            field: "restart".to_string(),
+            reason: format!("expected one of {} (optionally on-failure:N), got '{}'", RESTART_POLICIES.join(", "), policy)
+        });
+    }
+
+    Ok(Some(policy))
+}
+
+/// Parses a duration such as `90`, `45s`, `30m`, `2h` or `7d` into seconds.
+fn parse_duration(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (number, multiplier) = match value.chars().last()? {
+        's' => (&value[..value.len() - 1], 1),
+        'm' => (&value[..value.len() - 1], 60),
+        'h' => (&value[..value.len() - 1], 60 * 60),
+        'd' => (&value[..value.len() - 1], 24 * 60 * 60),
+        _ => (value, 1),
+    };
+
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+fn get_optional_duration(table: &HashMap<String, config::Value>, field: &str, file: &str) -> Result<Option<u64>, Error> {
+    match get_optional_string(table, field, file)? {
+        None => Ok(None),
+        Some(value) => parse_duration(&value)
+            .map(Some)
+            .ok_or_else(|| Error::ConfigInvalidValue {
+                file: file.to_string(),
+                field: field.to_string(),
+                reason: format!("expected a duration like 90s, 30m, 2h or 1d, got '{}'", value)
+            })
+    }
+}
+
 fn main() {
     if let Err(err) = run() {
         eprintln!("{}", err);
@@ -216,8 +645,8 @@ fn main() {
     }
 }
 
-fn run() -> Result<bool, Error> {
-    let matches = App::new("contain")
+fn build_cli() -> App<'static, 'static> {
+    App::new("contain")
         .setting(AppSettings::SubcommandRequiredElseHelp)
         .setting(AppSettings::DisableVersion)
         .version(env!("CARGO_PKG_VERSION"))
@@ -245,6 +674,36 @@ fn run() -> Result<bool, Error> {
             .long("skip-name")
             .help("Skip container name")
             .global(true))
+        .arg(Arg::with_name("image")
+            .long("image")
+            .help("Use this image instead of the configured one")
+            .takes_value(true)
+            .value_name("ref")
+            .global(true))
+        .arg(Arg::with_name("entry")
+            .long("entry")
+            .help("Use the images entry with this name or image, for any command")
+            .takes_value(true)
+            .value_name("name")
+            .global(true))
+        .arg(Arg::with_name("workdir")
+            .long("workdir")
+            .help("Mount the project at this path in the container")
+            .takes_value(true)
+            .value_name("path")
+            .global(true))
+        .arg(Arg::with_name("config")
+            .long("config")
+            .help("Use this configuration file instead of searching for .contain.yaml")
+            .takes_value(true)
+            .value_name("file")
+            .global(true))
+        .arg(Arg::with_name("profile")
+            .long("profile")
+            .help("Apply this profile from the configuration")
+            .takes_value(true)
+            .value_name("name")
+            .global(true))
         .arg(Arg::with_name("env")
             .short("e")
             .help("Set environment variable (-eVAR=value)")
@@ -274,13 +733,123 @@ fn run() -> Result<bool, Error> {
             .about("Open interactive shell (uses default_shell from config or /bin/bash)"))
         // up subcommand
         .subcommand(SubCommand::with_name("up")
-            .about("Start the container in the background and keep it running"))
+            .about("Start the container in the background and keep it running")
+            .arg(Arg::with_name("recreate")
+                .long("recreate")
+                .help("Remove and recreate the container, e.g. after .contain.yaml has changed")))
         // down subcommand
         .subcommand(SubCommand::with_name("down")
             .about("Stop and remove the background container"))
         // status subcommand
         .subcommand(SubCommand::with_name("status")
             .about("Show the status of the background container"))
+        // logs subcommand
+        .subcommand(SubCommand::with_name("logs")
+            .about("Show the output of the background container")
+            .arg(Arg::with_name("follow")
+                .short("f")
+                .long("follow")
+                .help("Follow log output"))
+            .arg(Arg::with_name("since")
+                .long("since")
+                .takes_value(true)
+                .value_name("TIME")
+                .help("Show logs since timestamp (e.g. 2013-01-02T13:23:37Z) or relative (e.g. 42m)")))
+        // stop subcommand
+        .subcommand(SubCommand::with_name("stop")
+            .about("Stop the background container without removing it"))
+        // restart subcommand
+        .subcommand(SubCommand::with_name("restart")
+            .about("Restart the background container"))
+        // attach subcommand
+        .subcommand(SubCommand::with_name("attach")
+            .about("Attach to the main process of the background container"))
+        // gc subcommand
+        .subcommand(SubCommand::with_name("gc")
+            .about("Stop idle background containers and remove what contain left behind")
+            .arg(Arg::with_name("older-than")
+                .long("older-than")
+                .takes_value(true)
+                .value_name("DURATION")
+                .help("Also remove containers kept with --keep that were created longer ago than this, e.g. 7d")))
+        // shims subcommand
+        .subcommand(SubCommand::with_name("shims")
+            .about("Generate wrappers that proxy the configured commands through 'contain run'")
+            .arg(Arg::with_name("global")
+                .long("global")
+                .help("Use ~/.local/share/contain/shims instead of the project-local .contain/bin")
+                .global(true))
+            .subcommand(SubCommand::with_name("install")
+                .about("Write a shim for every entry in 'commands:' (default)"))
+            .subcommand(SubCommand::with_name("remove")
+                .about("Remove previously generated shims")))
+        // hook subcommand
+        .subcommand(SubCommand::with_name("hook")
+            .about("Print a shell hook that adds project shims to PATH when changing directory")
+            .arg(Arg::with_name("shell")
+                .possible_values(&["bash", "zsh", "fish"])
+                .required(true)))
+        // ps subcommand
+        .subcommand(SubCommand::with_name("ps")
+            .about("List containers created by contain, across all projects")
+            .arg(Arg::with_name("project")
+                .long("project")
+                .help("Only list containers of the current project")))
+        // config subcommand
+        .subcommand(SubCommand::with_name("config")
+            .about("Inspect the configuration")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("show")
+                .about("Show the resolved image entries, with the active profile applied")
+                .arg(Arg::with_name("command")
+                    .help("Only show the entry used for this command"))))
+        // lock subcommand
+        .subcommand(SubCommand::with_name("lock")
+            .about("Manage image digests pinned in .contain.lock")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("update")
+                .about("Pull the configured images and pin their current digests")))
+        // push subcommand
+        .subcommand(SubCommand::with_name("push")
+            .about("Tag and push the configured image to its registry"))
+        // images subcommand
+        .subcommand(SubCommand::with_name("images")
+            .about("Manage images built by contain for this project")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("ls")
+                .about("List images built for this project"))
+            .subcommand(SubCommand::with_name("prune")
+                .about("Remove old builds, keeping the newest ones per image")
+                .arg(Arg::with_name("keep-last")
+                    .long("keep-last")
+                    .value_name("N")
+                    .default_value("1")
+                    .help("Number of builds to keep per configured image"))))
+        // init subcommand
+        .subcommand(SubCommand::with_name("init")
+            .about("Create a .contain.yaml and Dockerfile for the project in the current directory")
+            .arg(Arg::with_name("template")
+                .long("template")
+                .takes_value(true)
+                .possible_values(&["node", "python", "rust", "java", "gradle", "go"])
+                .help("Project type, detected from files like package.json or Cargo.toml by default"))
+            .arg(Arg::with_name("force")
+                .long("force")
+                .help("Overwrite existing files")))
+        // completions subcommand
+        .subcommand(SubCommand::with_name("completions")
+            .about("Print a shell completion script")
+            .arg(Arg::with_name("shell")
+                .possible_values(&["bash", "zsh", "fish"])
+                .required(true)))
+}
+
+fn run() -> Result<bool, Error> {
+    let matches = build_cli()
+        // Used by the completion scripts to list the commands routed by the nearest config.
+        // Added here rather than in build_cli() so it stays out of the generated scripts.
+        .subcommand(SubCommand::with_name("complete-commands")
+            .setting(AppSettings::Hidden))
         .get_matches();
 
     // Extract global options
@@ -294,8 +863,29 @@ fn run() -> Result<bool, Error> {
         cli_env_variables: matches.values_of("env")
             .map(|v| v.map(String::from).collect())
             .unwrap_or_default(),
+        overrides: ConfigOverrides {
+            image: matches.value_of("image").map(String::from),
+            entry: matches.value_of("entry").map(String::from),
+            workdir: matches.value_of("workdir").map(String::from)
+                .or_else(|| env::var("WORKDIR_PATH").ok()),
+            config: matches.value_of("config").map(String::from)
+                .or_else(|| env::var("CONTAIN_CONFIG").ok().filter(|file| !file.is_empty()))
+                .map(|file| env::current_dir().unwrap_or_default().join(file)),
+            profile: matches.value_of("profile").map(String::from)
+                .or_else(|| env::var("CONTAIN_PROFILE").ok()),
+            skip_var_commands: false,
+        },
     };
 
+    // Opportunistically stop idle background containers, at most once per interval. Not for gc,
+    // which does it anyway, for commands whose output is evaluated by the shell, nor for
+    // commands that only read or write configuration.
+    let quiet_commands = ["gc", "hook", "completions", "complete-commands", "shims", "config", "init"];
+    if !matches.subcommand_name().is_some_and(|name| quiet_commands.contains(&name))
+        && !options.dry_run && !is_inside_container() {
+        reap_idle_containers_throttled();
+    }
+
     match matches.subcommand() {
         ("run", Some(sub_matches)) => {
             // Handle --help explicitly since we disabled automatic -h
@@ -341,8 +931,8 @@ fn run() -> Result<bool, Error> {
                 .map_err(|e| Error::PathError(format!("Failed to get current directory: {}", e)))?;
 
             // Try to load config with "any" matcher or "shell" command
-            let config = load_config(current_path.clone(), "shell")
-                .or_else(|_| load_config(current_path, "any"))?;
+            let config = load_config(current_path.clone(), "shell", &options.overrides)
+                .or_else(|_| load_config(current_path, "any", &options.overrides))?;
 
             let shell = config.default_shell.as_deref().unwrap_or(DEFAULT_SHELL);
 
@@ -353,8 +943,8 @@ fn run() -> Result<bool, Error> {
 
             run_command(shell, vec![], options)
         }
-        ("up", Some(_sub_matches)) => {
-            container_up(options)
+        ("up", Some(sub_matches)) => {
+            container_up(options, sub_matches.is_present("recreate"))
         }
         ("down", Some(_sub_matches)) => {
             container_down(options)
@@ -362,10 +952,272 @@ fn run() -> Result<bool, Error> {
         ("status", Some(_sub_matches)) => {
             container_status(options)
         }
+        ("logs", Some(sub_matches)) => {
+            container_logs(options, sub_matches.is_present("follow"), sub_matches.value_of("since"))
+        }
+        ("stop", Some(_sub_matches)) => {
+            container_stop(options)
+        }
+        ("restart", Some(_sub_matches)) => {
+            container_restart(options)
+        }
+        ("attach", Some(_sub_matches)) => {
+            container_attach(options)
+        }
+        ("gc", Some(sub_matches)) => {
+            let older_than = match sub_matches.value_of("older-than") {
+                Some(value) => Some(parse_duration(value).ok_or_else(|| Error::UnsupportedParameters(
+                    format!("--older-than expects a duration like 12h or 7d, got '{}'", value)))?),
+                None => None,
+            };
+            container_gc(options, older_than)
+        }
+        ("shims", Some(sub_matches)) => {
+            match sub_matches.subcommand() {
+                ("remove", Some(remove_matches)) => {
+                    shims_remove(options, sub_matches.is_present("global") || remove_matches.is_present("global"))
+                }
+                (_, install_matches) => {
+                    let global = sub_matches.is_present("global")
+                        || install_matches.is_some_and(|m| m.is_present("global"));
+                    shims_install(options, global)
+                }
+            }
+        }
+        ("hook", Some(sub_matches)) => {
+            print_shell_hook(sub_matches.value_of("shell").unwrap());
+            Ok(true)
+        }
+        ("ps", Some(sub_matches)) => container_ps(options, sub_matches.is_present("project")),
+        ("config", Some(sub_matches)) => {
+            match sub_matches.subcommand() {
+                ("show", Some(show_matches)) => config_show(options, show_matches.value_of("command")),
+                _ => unreachable!()
+            }
+        }
+        ("lock", Some(sub_matches)) => {
+            match sub_matches.subcommand() {
+                ("update", Some(_update_matches)) => lock_update(options),
+                _ => unreachable!()
+            }
+        }
+        ("push", Some(_sub_matches)) => push_image(options),
+        ("images", Some(sub_matches)) => {
+            match sub_matches.subcommand() {
+                ("ls", Some(_ls_matches)) => images_ls(options),
+                ("prune", Some(prune_matches)) => {
+                    let keep = prune_matches.value_of("keep-last").unwrap();
+                    let keep = keep.parse::<usize>()
+                        .map_err(|_| Error::UnsupportedParameters(format!("Number of builds to keep must be a number, got '{}'", keep)))?;
+                    images_prune(options, keep)
+                }
+                _ => unreachable!()
+            }
+        }
+        ("init", Some(sub_matches)) => {
+            contain_init(options, sub_matches.value_of("template"), sub_matches.is_present("force"))
+        }
+        ("completions", Some(sub_matches)) => {
+            print_completions(sub_matches.value_of("shell").unwrap());
+            Ok(true)
+        }
+        ("complete-commands", Some(_sub_matches)) => {
+            for command in routed_commands(&options.overrides) {
+                println!("{}", command);
+            }
+            Ok(true)
+        }
         _ => unreachable!()
     }
 }
 
+/// Lists every command routed by the `commands:` entries of a config, excluding `any`.
+fn get_config_commands(config: &config::Config) -> Vec<String> {
+    let mut commands: Vec<String> = Vec::new();
+    let array = config.get_array("images").unwrap_or_default();
+
+    for node in array {
+        let commands_value = match node.into_table().ok().and_then(|t| t.get("commands").cloned()) {
+            Some(v) => v,
+            None => continue,
+        };
+
+        let entries = match commands_value.clone().into_string() {
+            Ok(string) => vec![string],
+            Err(_) => commands_value.into_array()
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|entry| entry.into_string().ok())
+                .collect(),
+        };
+
+        for entry in entries {
+            if entry != "any" && !commands.contains(&entry) {
+                commands.push(entry);
+            }
+        }
+    }
+
+    commands
+}
+
+/// The file names looked for in each directory, for error messages.
+fn config_file_names() -> String {
+    CONFIG_FILENAMES.iter().copied()
+        .chain(EMBEDDED_CONFIG_SOURCES.iter().map(|(name, _)| *name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The configuration file used for the project rooted at `root_path`: the explicit one,
+/// the first of CONFIG_FILENAMES that exists there, or else a manifest that embeds one.
+fn config_file_path(root_path: &Path, overrides: &ConfigOverrides) -> PathBuf {
+    overrides.config.clone().unwrap_or_else(|| {
+        CONFIG_FILENAMES.iter()
+            .map(|name| root_path.join(name))
+            .find(|path| path.is_file())
+            .or_else(|| EMBEDDED_CONFIG_SOURCES.iter()
+                .map(|(name, _)| root_path.join(name))
+                .find(|path| matches!(read_config_file(path), Ok(Some(_)))))
+            .unwrap_or_else(|| root_path.join(CONTAIN_FILENAME))
+    })
+}
+
+/// Reads a configuration file in any format the config crate supports. For the manifests in
+/// EMBEDDED_CONFIG_SOURCES only the embedded table is used; Ok(None) means the file does not
+/// exist or does not embed a contain configuration.
+fn read_config_file(file: &Path) -> Result<Option<config::Config>, Error> {
+    if !file.is_file() {
+        return Ok(None);
+    }
+
+    let config = config::Config::builder()
+        .add_source(config::File::from(file))
+        .build()
+        .map_err(|e| Error::ConfigError(format!("{}: {}", file.display(), e)))?;
+
+    let file_name = file.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let keys = match EMBEDDED_CONFIG_SOURCES.iter().find(|(name, _)| *name == file_name) {
+        Some((_, keys)) => keys,
+        None => return Ok(Some(config)),
+    };
+
+    let table = match keys.iter().find_map(|key| config.get_table(key).ok()) {
+        Some(table) => table,
+        None => return Ok(None),
+    };
+
+    let mut builder = config::Config::builder();
+    for (key, value) in table {
+        builder = builder.set_default(key, value)
+            .map_err(|e| Error::ConfigError(format!("{}: {}", file.display(), e)))?;
+    }
+
+    builder.build()
+        .map(Some)
+        .map_err(|e| Error::ConfigError(format!("{}: {}", file.display(), e)))
+}
+
+fn config_dir() -> Option<PathBuf> {
+    let base = match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var("HOME").ok()?).join(".config"),
+    };
+
+    Some(base.join("contain"))
+}
+
+/// The highest directory upward discovery may reach from the current directory, based on
+/// `discovery_stop:` in the user configuration or CONTAIN_DISCOVERY_STOP:
+/// `git` stops at the enclosing repository root, `home` stops below $HOME, and any other
+/// value is a directory to stop at. Without a setting, discovery continues up to `/`.
+fn discovery_limit() -> Result<Option<PathBuf>, Error> {
+    let setting = match env::var("CONTAIN_DISCOVERY_STOP") {
+        Ok(setting) => Some(setting),
+        Err(_) => config_dir()
+            .map(|dir| dir.join(USER_CONFIG_FILENAME))
+            .filter(|path| path.is_file())
+            .map(|path| config::Config::builder()
+                .add_source(config::File::from(path.clone()))
+                .build()
+                .map_err(|e| Error::ConfigError(format!("{}: {}", path.display(), e))))
+            .transpose()?
+            .and_then(|config| config.get_string("discovery_stop").ok()),
+    };
+
+    let current_path = env::current_dir()
+        .map_err(|e| Error::PathError(format!("Failed to get current directory: {}", e)))?;
+
+    Ok(match setting.as_deref() {
+        None | Some("") | Some("none") => None,
+        Some("git") => current_path.ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .map(Path::to_path_buf),
+        Some("home") => {
+            let home = PathBuf::from(env::var("HOME").unwrap_or_default());
+            // The child of $HOME that contains the current directory, or $HOME itself
+            current_path.ancestors()
+                .find(|dir| dir.parent() == Some(home.as_path()))
+                .map(Path::to_path_buf)
+                .or_else(|| Some(home).filter(|home| home == &current_path))
+        }
+        Some(dir) => Some(PathBuf::from(shellexpand::tilde(dir).into_owned())),
+    })
+}
+
+/// Finds the nearest configuration file at or above `path`, regardless of which commands it routes.
+fn find_config_file(mut path: PathBuf, overrides: &ConfigOverrides) -> Result<(PathBuf, config::Config), Error> {
+    let limit = discovery_limit()?;
+    if let Some(file) = &overrides.config {
+        path = file.parent().map(Path::to_path_buf).unwrap_or_default();
+    }
+
+    loop {
+        if let Some(config) = read_config_file(&config_file_path(&path, overrides))? {
+            return Ok((path, config));
+        }
+
+        if overrides.config.is_some() || limit.as_ref() == Some(&path) || !path.pop() {
+            return Err(Error::NoConfigFileFound);
+        }
+    }
+}
+
+/// Parses every `images[]` entry of the nearest configuration file, in file order.
+fn load_all_configs(path: PathBuf, overrides: &ConfigOverrides) -> Result<Vec<Configuration>, Error> {
+    let (root_path, config) = find_config_file(path, overrides)?;
+    let full_path = config_file_path(&root_path, overrides).to_string_lossy().into_owned();
+
+    let profile = active_profile(&config, &full_path, overrides)?;
+    let mut entries = Vec::new();
+    for node in config.get_array("images").unwrap_or_default() {
+        let mut entry = match node.into_table() {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if let Some(profile) = &profile {
+            apply_profile(&config, profile, &mut entry, &full_path)?;
+        }
+        entries.push(parse_image_entry(entry, root_path.clone(), &full_path, profile.clone(), overrides)?);
+    }
+
+    Ok(entries)
+}
+
+/// Finds the entry picked with --entry by its name or image, as written or as expanded for
+/// the project in `templates`, which is what the `contain.entry` label of its containers holds.
+fn get_entry_table(config: &config::Config, entry: &str, templates: &TemplateContext) -> Option<HashMap<String, config::Value>> {
+    config.get_array("images").ok()?
+        .into_iter()
+        .filter_map(|node| node.into_table().ok())
+        .find(|table| ["name", "image"].iter().any(|field| {
+            table.get(*field)
+                .and_then(|v| v.clone().into_string().ok())
+                .is_some_and(|value| value == entry
+                    || expand_template(&value, "", field, templates).is_ok_and(|expanded| expanded == entry))
+        }))
+}
+
 fn get_config_table(config: &config::Config, command: &str) -> Option<HashMap<String, config::Value>> {
     let array = config.get_array("images").ok()?;
 
@@ -388,10 +1240,9 @@ fn get_config_table(config: &config::Config, command: &str) -> Option<HashMap<St
         // Check if commands is an array of strings
         } else if let Ok(entries) = commands_value.into_array() {
             for entry in &entries {
-                if let Ok(entry_string) = entry.clone().into_string() {
-                    if entry_string == command || entry_string == "any" {
-                        return Some(table.clone())
-                    }
+                if let Ok(entry_string) = entry.clone().into_string()
+                    && (entry_string == command || entry_string == "any") {
+                    return Some(table.clone())
                 }
             }
         }
@@ -401,685 +1252,3924 @@ fn get_config_table(config: &config::Config, command: &str) -> Option<HashMap<St
     None
 }
 
-fn load_config(mut path: PathBuf, command: &str) -> Result<Configuration, Error> {
-
-    let path_str = path.as_path()
-        .to_str()
-        .ok_or_else(|| Error::PathError("Path contains invalid UTF-8".to_string()))?;
+fn load_config(path: PathBuf, command: &str, overrides: &ConfigOverrides) -> Result<Configuration, Error> {
+    let limit = discovery_limit()?;
+    find_command_config(path, command, overrides, limit.as_deref())
+}
 
-    // SAFETY: This is single-threaded CLI startup code
-    unsafe { env::set_var("CONTAIN_ROOT_PATH", path_str); }
+/// Searches upward from `path` for the entry routing `command`, stopping at `limit`.
+fn find_command_config(mut path: PathBuf, command: &str, overrides: &ConfigOverrides, limit: Option<&Path>) -> Result<Configuration, Error> {
+    let explicit = &overrides.config;
+    if let Some(file) = explicit {
+        path = file.parent().map(Path::to_path_buf).unwrap_or_default();
+    }
 
-    let full_path = format!("{}/{}", path_str, CONTAIN_FILENAME);
+    let full_path = config_file_path(&path, overrides).to_string_lossy().into_owned();
 
-    let result = config::Config::builder()
-        .add_source(config::File::with_name(&full_path))
-        .build();
+    let result = read_config_file(Path::new(&full_path));
 
-    if let Ok(ref config) = result {
+    if let Ok(Some(ref config)) = result {
 
         let min_version: Option<String> = config.get("contain_min_version").ok();
 
-        if let Some(v) = min_version {
-            if Version::parse(env!("CARGO_PKG_VERSION")) < Version::parse(&v) {
-                return Err(Error::ConfigError(format!(
-                    "{} requires contain version >= {} (current version: {})",
-                    full_path, v, env!("CARGO_PKG_VERSION")
-                )));
-            }
-        };
-
-        if let Some(command_entry) = get_config_table(config, command) {
-
-            let image = get_required_string(&command_entry, "image", &full_path)?;
-            let name = get_optional_string(&command_entry, "name", &full_path)?;
-            let dockerfile = get_required_string(&command_entry, "dockerfile", &full_path)?;
-            let default_shell = get_optional_string(&command_entry, "default_shell", &full_path)?;
-
-            // Process var definitions (execute commands to set environment variables)
-            if let Some(node) = command_entry.get("var") {
-                if let Ok(vec) = node.clone().into_array() {
-                    for item in &vec {
-                        if let Ok(obj) = item.clone().into_table() {
-                            let var_name = obj.get("name")
-                                .ok_or_else(|| Error::ConfigMissingField {
-                                    file: full_path.clone(),
-                                    field: "var[].name".to_string()
-                                })?;
-                            let var_cmd = obj.get("command")
-                                .ok_or_else(|| Error::ConfigMissingField {
-                                    file: full_path.clone(),
-                                    field: "var[].command".to_string()
-                                })?;
-
-                            let var_name_string = var_name.to_string();
-                            let var_cmd_string = shellexpand::env(&var_cmd.to_string())
-                                .map_err(|e| Error::ConfigInvalidValue {
-                                    file: full_path.clone(),
-                                    field: "var[].command".to_string(),
-                                    reason: format!("environment variable expansion failed: {}", e)
-                                })?
-                                .into_owned();
-
-                            let result = Command::new("sh")
-                                .arg("-c")
-                                .arg(&var_cmd_string)
-                                .output()
-                                .map_err(|e| Error::CommandError {
-                                    cmd: format!("sh -c '{}'", var_cmd_string),
-                                    reason: e.to_string()
-                                })?;
-
-                            let output = String::from_utf8_lossy(&result.stdout)
-                                .to_string()
-                                .trim()
-                                .to_string();
-
-                            // SAFETY: This is single-threaded CLI startup code
-                            unsafe { env::set_var(var_name_string, output); }
-                        }
-                    }
-                }
-            }
-
-            let env_variables = get_string_array(&command_entry, "env", &full_path)?;
-            let build_args = get_string_array(&command_entry, "build_args", &full_path)?;
-
-            // Process mounts
-            let mut extra_mounts: Vec<String> = Vec::new();
-            if let Some(node) = command_entry.get("mounts") {
-                if let Ok(vec) = node.clone().into_array() {
-                    for (i, item) in vec.iter().enumerate() {
-                        if let Ok(obj) = item.clone().into_table() {
-                            let mount_type = obj.get("type")
-                                .ok_or_else(|| Error::ConfigMissingField {
-                                    file: full_path.clone(),
-                                    field: format!("mounts[{}].type", i)
-                                })?;
-                            let src = obj.get("src")
-                                .ok_or_else(|| Error::ConfigMissingField {
-                                    file: full_path.clone(),
-                                    field: format!("mounts[{}].src", i)
-                                })?;
-                            let dst = obj.get("dst")
-                                .ok_or_else(|| Error::ConfigMissingField {
-                                    file: full_path.clone(),
-                                    field: format!("mounts[{}].dst", i)
-                                })?;
-
-                            let src_string = src.to_string();
-                            let dst_string = dst.to_string();
-
-                            let src_expanded = shellexpand::env(&src_string)
-                                .map_err(|e| Error::ConfigInvalidValue {
-                                    file: full_path.clone(),
-                                    field: format!("mounts[{}].src", i),
-                                    reason: format!("environment variable expansion failed: {}", e)
-                                })?;
-                            let dst_expanded = shellexpand::env(&dst_string)
-                                .map_err(|e| Error::ConfigInvalidValue {
-                                    file: full_path.clone(),
-                                    field: format!("mounts[{}].dst", i),
-                                    reason: format!("environment variable expansion failed: {}", e)
-                                })?;
-
-                            let extra_options = match obj.get("options") {
-                                Some(s) => format!(",{}", s.to_string()),
-                                None => "".to_string()
-                            };
-
-                            extra_mounts.push(format!("type={},src={},dst={}{}", mount_type, src_expanded, dst_expanded, extra_options));
-                        }
-                    }
-                }
-            }
+        if let Some(v) = min_version
+            && Version::parse(env!("CARGO_PKG_VERSION")) < Version::parse(&v) {
+            return Err(Error::ConfigError(format!(
+                "{} requires contain version >= {} (current version: {})",
+                full_path, v, env!("CARGO_PKG_VERSION")
+            )));
+        }
 
-            // Process ports
-            let mut ports: Vec<String> = Vec::new();
-            if let Some(node) = command_entry.get("ports") {
-                if let Ok(vec) = node.clone().into_array() {
-                    for item in &vec {
-                        ports.push(item.to_string());
-                    }
-                }
-            }
+        // An entry picked with --entry is used for any command
+        let selected = match overrides.entry.as_deref() {
+            Some(entry) => get_entry_table(config, entry, &TemplateContext::new(&path, overrides)),
+            None => get_config_table(config, command),
+        };
 
-            // Process flags
-            let mut flags: Vec<String> = Vec::new();
-            if let Some(node) = command_entry.get("flags") {
-                if let Ok(vec) = node.clone().into_array() {
-                    for item in &vec {
-                        flags.push(item.to_string());
-                    }
-                }
+        if let Some(mut command_entry) = selected {
+            let profile = active_profile(config, &full_path, overrides)?;
+            if let Some(profile) = &profile {
+                apply_profile(config, profile, &mut command_entry, &full_path)?;
             }
 
-            let workdir_path = env::var("WORKDIR_PATH").unwrap_or_else(|_| "/workdir".to_owned());
-
-            let config_struct = Configuration {
-                image,
-                name,
-                dockerfile,
-                root_path: path,
-                workdir_path,
-                flags,
-                env_variables,
-                build_args,
-                extra_mounts,
-                ports,
-                default_shell,
-            };
-
-            return Ok(config_struct);
+            parse_image_entry(command_entry, path, &full_path, profile, overrides)
+        } else if explicit.is_some() || limit == Some(path.as_path()) {
+            // No discovery with --config, nor beyond discovery_stop
+            Err(no_config_found(command, overrides))
         } else {
             // Command not found in this config, try parent directory
             path.pop();
-            return load_config(path, command);
+            find_command_config(path, command, overrides, limit)
         }
+    } else if explicit.is_some() {
+        Err(result.err().unwrap_or_else(|| Error::ConfigError(format!("{}: no contain configuration found", full_path))))
     } else {
         // No config file at this path, try parent directory
-        if path.as_os_str().len() > 1 {
+        if path.as_os_str().len() > 1 && limit != Some(path.as_path()) {
             path.pop();
-            return load_config(path, command);
+            find_command_config(path, command, overrides, limit)
         } else {
             // Reached root without finding config
-            return Err(Error::NoConfigFound { command: command.to_string() });
+            Err(no_config_found(command, overrides))
         }
-    };
+    }
 }
 
-fn image_exists(image: &String) -> Result<bool, Error> {
-    let status = Command::new("docker")
-        .arg("image")
-        .arg("inspect")
-        .arg(image)
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .map_err(|e| Error::CommandError {
-            cmd: format!("docker image inspect {}", image),
-            reason: e.to_string()
-        })?;
+/// Turns a single `images[]` entry into a Configuration for the project at `path`.
+fn parse_image_entry(command_entry: HashMap<String, config::Value>, path: PathBuf, full_path: &str, profile: Option<String>, overrides: &ConfigOverrides) -> Result<Configuration, Error> {
+    let mut templates = TemplateContext::new(&path, overrides);
+    let workdir_path = templates.workdir.clone();
 
-    Ok(status.success())
-}
+    // --image replaces the configured image for this invocation only
+    let image_overridden = overrides.image.is_some();
+    let image = match overrides.image.clone() {
+        Some(image) => image,
+        None => expand_template(&get_required_string(&command_entry, "image", full_path)?, full_path, "image", &templates)?,
+    };
+    let name = get_optional_string(&command_entry, "name", full_path)?
+        .map(|name| expand_template(&name, full_path, "name", &templates))
+        .transpose()?;
+    // Identifies the entry on its containers, in the form accepted by --entry
+    let entry = match (&name, image_overridden) {
+        (Some(name), _) => name.clone(),
+        (None, false) => image.clone(),
+        (None, true) => get_required_string(&command_entry, "image", full_path)?,
+    };
+    let dockerfile = get_optional_string(&command_entry, "dockerfile", full_path)?;
+    let dockerfile_inline = get_optional_string(&command_entry, "dockerfile_inline", full_path)?;
+    match (&dockerfile, &dockerfile_inline) {
+        (None, None) => return Err(Error::ConfigMissingField {
+            file: full_path.to_string(),
+            field: "dockerfile".to_string()
+        }),
+        (Some(_), Some(_)) => return Err(Error::ConfigInvalidValue {
+            file: full_path.to_string(),
+            field: "dockerfile_inline".to_string(),
+            reason: "cannot be combined with 'dockerfile'".to_string()
+        }),
+        _ => {}
+    }
+    let build_context = get_optional_string(&command_entry, "context", full_path)?;
+    let build_target = get_optional_string(&command_entry, "target", full_path)?;
+    let default_shell = get_optional_string(&command_entry, "default_shell", full_path)?;
+    let up_command = get_optional_command(&command_entry, "up_command", full_path, &templates)?;
+    let restart = get_restart_policy(&command_entry, full_path)?;
+    let idle_timeout = get_optional_duration(&command_entry, "idle_timeout", full_path)?;
+    let autostart = get_optional_bool(&command_entry, "autostart", full_path)?.unwrap_or(false);
+    let user_layer = match get_optional_string(&command_entry, "user_layer", full_path)?.as_deref() {
+        None | Some("none") => false,
+        Some("auto") => true,
+        Some(other) => return Err(Error::ConfigInvalidValue {
+            file: full_path.to_string(),
+            field: "user_layer".to_string(),
+            reason: format!("expected 'auto' or 'none', got '{}'", other)
+        }),
+    };
+    let user_layer_sudo = get_optional_bool(&command_entry, "user_layer_sudo", full_path)?.unwrap_or(false);
+    let tag_content_hash = get_optional_bool(&command_entry, "tag_content_hash", full_path)?.unwrap_or(false);
+    let registry = get_optional_string(&command_entry, "registry", full_path)?
+        .map(|r| r.trim_end_matches('/').to_string());
+    let push_tags = get_string_array(&command_entry, "tags", full_path, &templates)?;
+
+    // Process var definitions (execute commands to set environment variables)
+    if let Some(node) = command_entry.get("var")
+        && let Ok(vec) = node.clone().into_array() {
+        for item in &vec {
+            if let Ok(obj) = item.clone().into_table() {
+                let var_name = obj.get("name")
+                    .ok_or_else(|| Error::ConfigMissingField {
+                        file: full_path.to_string(),
+                        field: "var[].name".to_string()
+                    })?;
+                let var_cmd = obj.get("command")
+                    .ok_or_else(|| Error::ConfigMissingField {
+                        file: full_path.to_string(),
+                        field: "var[].command".to_string()
+                    })?;
 
-fn download_image(image: &String) -> Result<bool, Error> {
-    println!("Downloading image: {}", image);
-    let status = Command::new("docker")
-        .arg("pull")
-        .arg(image)
-        .status()
-        .map_err(|e| Error::CommandError {
-            cmd: format!("docker pull {}", image),
-            reason: e.to_string()
-        })?;
+                let var_name_string = var_name.to_string();
 
-    Ok(status.success())
-}
+                // Displaying the configuration must not run anything, show the command instead
+                if overrides.skip_var_commands {
+                    templates.unevaluated.insert(var_name_string, format!("$({})", var_cmd));
+                    continue;
+                }
 
-fn container_exists(name: &String) -> Result<bool, Error> {
-    let result = Command::new("docker")
-        .arg("ps")
-        .arg("-f")
-        .arg(format!("name={}", name))
-        .arg("--format")
-        .arg("'{{.Names}}'")
-        .output()
-        .map_err(|e| Error::CommandError {
-            cmd: format!("docker ps -f name={}", name),
-            reason: e.to_string()
-        })?;
+                let var_cmd_string = expand_template(&var_cmd.to_string(), full_path, "var[].command", &templates)?;
 
-    let output = String::from_utf8_lossy(&result.stdout)
-        .to_string()
-        .trim()
-        .to_string()
-        .replace("'", "");
+                let result = Command::new("sh")
+                    .arg("-c")
+                    .arg(&var_cmd_string)
+                    .env("CONTAIN_ROOT_PATH", &path)
+                    .output()
+                    .map_err(|e| Error::CommandError {
+                        cmd: format!("sh -c '{}'", var_cmd_string),
+                        reason: e.to_string()
+                    })?;
 
-    Ok(&output == name)
-}
+                let output = String::from_utf8_lossy(&result.stdout)
+                    .to_string()
+                    .trim()
+                    .to_string();
+
+                // SAFETY: This is single-threaded CLI startup code
+                unsafe { env::set_var(var_name_string, output); }
+            }
+        }
+    }
+
+    let env_variables = get_string_array(&command_entry, "env", full_path, &templates)?;
+    let build_args = get_string_array(&command_entry, "build_args", full_path, &templates)?;
+    let cache_from = get_string_array(&command_entry, "cache_from", full_path, &templates)?;
+    let build_secrets = get_string_array(&command_entry, "secrets", full_path, &templates)?;
+
+    // BuildKit/buildx build options
+    let buildx = match get_optional_string(&command_entry, "builder", full_path)?.as_deref() {
+        None | Some("docker") => false,
+        Some("buildx") => true,
+        Some(other) => return Err(Error::ConfigInvalidValue {
+            file: full_path.to_string(),
+            field: "builder".to_string(),
+            reason: format!("expected 'docker' or 'buildx', got '{}'", other)
+        }),
+    };
+    let buildx_builder = get_optional_string(&command_entry, "buildx_builder", full_path)?;
+    let platform = get_optional_string(&command_entry, "platform", full_path)?;
+    let build_ssh = get_string_array(&command_entry, "ssh", full_path, &templates)?;
+    let cache_to = get_string_array(&command_entry, "cache_to", full_path, &templates)?;
+    let cache_dir = get_optional_string(&command_entry, "cache_dir", full_path)?;
+    let build_progress = get_optional_string(&command_entry, "progress", full_path)?;
+
+    // Exporting the build cache is only supported by buildx
+    if !buildx {
+        for field in ["buildx_builder", "cache_to", "cache_dir", "progress"] {
+            if command_entry.contains_key(field) {
+                return Err(Error::ConfigInvalidValue {
+                    file: full_path.to_string(),
+                    field: field.to_string(),
+                    reason: "requires 'builder: buildx'".to_string()
+                });
+            }
+        }
+    }
+
+    // Process mounts
+    let mut extra_mounts: Vec<String> = Vec::new();
+    if let Some(node) = command_entry.get("mounts")
+        && let Ok(vec) = node.clone().into_array() {
+        for (i, item) in vec.iter().enumerate() {
+            if let Ok(obj) = item.clone().into_table() {
+                let mount_type = obj.get("type")
+                    .ok_or_else(|| Error::ConfigMissingField {
+                        file: full_path.to_string(),
+                        field: format!("mounts[{}].type", i)
+                    })?;
+                let src = obj.get("src")
+                    .ok_or_else(|| Error::ConfigMissingField {
+                        file: full_path.to_string(),
+                        field: format!("mounts[{}].src", i)
+                    })?;
+                let dst = obj.get("dst")
+                    .ok_or_else(|| Error::ConfigMissingField {
+                        file: full_path.to_string(),
+                        field: format!("mounts[{}].dst", i)
+                    })?;
 
-fn build_image(image: &String, dockerfile: &String, dockerfile_path: &PathBuf, workdir_path: &String, build_args: &Vec<String>) -> Result<bool, Error> {
-    let dockerfile_path_str = dockerfile_path.to_str()
-        .ok_or_else(|| Error::PathError("Dockerfile path contains invalid UTF-8".to_string()))?;
+                let src_string = src.to_string();
+                let dst_string = dst.to_string();
 
-    println!("Building image: {}/{} -> {}", dockerfile_path_str, dockerfile, image);
+                let src_expanded = expand_template(&src_string, full_path, &format!("mounts[{}].src", i), &templates)?;
+                let dst_expanded = expand_template(&dst_string, full_path, &format!("mounts[{}].dst", i), &templates)?;
 
-    let mut docker_args: Vec<&str> = vec!["build"];
+                let extra_options = match obj.get("options") {
+                    Some(s) => format!(",{}", s),
+                    None => "".to_string()
+                };
 
-    let uid = get_current_uid();
-    let result = get_user_by_uid(uid);
-    let username: String = match result {
-        None => "dev".to_string(),
-        Some(user) => user.name().to_str().unwrap_or("dev").to_owned()
+                extra_mounts.push(format!("type={},src={},dst={}{}", mount_type, src_expanded, dst_expanded, extra_options));
+            }
+        }
+    }
+
+    // Process ports
+    let mut ports: Vec<String> = Vec::new();
+    if let Some(node) = command_entry.get("ports")
+        && let Ok(vec) = node.clone().into_array() {
+        for item in &vec {
+            ports.push(item.to_string());
+        }
+    }
+
+    // Process flags
+    let mut flags: Vec<String> = Vec::new();
+    if let Some(node) = command_entry.get("flags")
+        && let Ok(vec) = node.clone().into_array() {
+        for item in &vec {
+            flags.push(item.to_string());
+        }
+    }
+
+
+    let pinned = if image_overridden { None } else { read_lock(&path).remove(&image) };
+
+    let config_struct = Configuration {
+        image,
+        name,
+        dockerfile,
+        dockerfile_inline,
+        build_context,
+        build_target,
+        cache_from,
+        build_secrets,
+        buildx,
+        buildx_builder,
+        platform,
+        build_ssh,
+        cache_to,
+        cache_dir,
+        build_progress,
+        root_path: path,
+        config_file: PathBuf::from(full_path),
+        workdir_path,
+        flags,
+        env_variables,
+        build_args,
+        extra_mounts,
+        ports,
+        default_shell,
+        up_command,
+        restart,
+        idle_timeout,
+        autostart,
+        user_layer,
+        user_layer_sudo,
+        tag_content_hash,
+        registry,
+        push_tags,
+        pinned,
+        image_overridden,
+        profile,
+        entry,
     };
 
-    let uid_str = format!("uid={}", uid);
-    let gid_str = format!("gid={}", get_current_gid());
-    let username_str = format!("username={}", username.as_str());
-    let workdir_path_str_arg = format!("workdir_path={}", workdir_path);
+    Ok(config_struct)
+}
 
-    docker_args.push("--build-arg");
-    docker_args.push(&uid_str);
-    docker_args.push("--build-arg");
-    docker_args.push(&gid_str);
-    docker_args.push("--build-arg");
-    docker_args.push(&username_str);
-    docker_args.push("--build-arg");
-    docker_args.push(&workdir_path_str_arg);
+/// The profile to apply: `--profile` or CONTAIN_PROFILE, or `ci` when running under CI=true
+/// and the configuration defines it. An empty name or `none` disables profiles.
+fn active_profile(config: &config::Config, full_path: &str, overrides: &ConfigOverrides) -> Result<Option<String>, Error> {
+    let profiles = config.get_table("profiles").unwrap_or_default();
+
+    match overrides.profile.clone() {
+        Some(name) if name.is_empty() || name == "none" => Ok(None),
+        Some(name) if profiles.contains_key(&name) => Ok(Some(name)),
+        Some(name) => Err(Error::ConfigInvalidValue {
+            file: full_path.to_string(),
+            field: "profiles".to_string(),
+            reason: format!("profile '{}' is not defined", name)
+        }),
+        None => {
+            let ci = env::var("CI").map(|v| v == "true" || v == "1").unwrap_or(false);
+            Ok(if ci && profiles.contains_key("ci") { Some("ci".to_string()) } else { None })
+        }
+    }
+}
 
-    if !build_args.is_empty() {
-        for item in build_args {
-            docker_args.push("--build-arg");
-            docker_args.push(item.trim());
+/// Overlays a profile onto an image entry. `env` is merged into the entry's variables, `tag`
+/// replaces the tag of its image, and every other field (ports, mounts, flags, ...) replaces
+/// the entry's value.
+fn apply_profile(config: &config::Config, profile: &str, entry: &mut HashMap<String, config::Value>, full_path: &str) -> Result<(), Error> {
+    let overlay = config.get_table("profiles").unwrap_or_default()
+        .remove(profile)
+        .map(|node| node.into_table())
+        .transpose()
+        .map_err(|_| Error::ConfigInvalidValue {
+            file: full_path.to_string(),
+            field: format!("profiles.{}", profile),
+            reason: "expected a table of image fields".to_string()
+        })?
+        .unwrap_or_default();
+
+    for (field, value) in overlay {
+        match field.as_str() {
+            "env" => {
+                let overlay_env = value.into_array().map_err(|_| Error::ConfigInvalidValue {
+                    file: full_path.to_string(),
+                    field: format!("profiles.{}.env", profile),
+                    reason: "expected an array".to_string()
+                })?;
+                let var_name = |item: &config::Value| item.to_string().split('=').next().unwrap_or_default().to_string();
+                let overridden: Vec<String> = overlay_env.iter().map(var_name).collect();
+
+                // Variables set by the profile replace those of the entry
+                let mut env_variables: Vec<config::Value> = entry.get("env").cloned()
+                    .and_then(|node| node.into_array().ok())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|item| !overridden.contains(&var_name(item)))
+                    .collect();
+                env_variables.extend(overlay_env);
+                entry.insert(field, config::Value::new(None, env_variables));
+            }
+            "tag" => {
+                let tag = value.into_string().map_err(|_| Error::ConfigInvalidValue {
+                    file: full_path.to_string(),
+                    field: format!("profiles.{}.tag", profile),
+                    reason: "expected a string".to_string()
+                })?;
+                let (repository, _) = split_image_reference(&get_required_string(entry, "image", full_path)?);
+                entry.insert("image".to_string(), config::Value::new(None, format!("{}:{}", repository, tag)));
+            }
+            _ => {
+                entry.insert(field, value);
+            }
         }
     }
 
-    docker_args.push("-t");
-    docker_args.push(image);
-    docker_args.push("-f");
-    docker_args.push(dockerfile);
-    docker_args.push(dockerfile_path_str);
+    Ok(())
+}
 
-    println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(&docker_args));
+fn no_config_found(command: &str, overrides: &ConfigOverrides) -> Error {
+    match (&overrides.entry, &overrides.config) {
+        (Some(entry), _) => Error::ConfigError(format!("No image entry with name or image '{}' found", entry)),
+        (None, Some(file)) => Error::ConfigError(format!("No docker image found for '{}' in {}", command, file.display())),
+        (None, None) => Error::NoConfigFound { command: command.to_string() },
+    }
+}
+
+fn image_exists(image: &str) -> Result<bool, Error> {
+    Ok(inspect_image(image)?.is_some())
+}
 
+fn download_image(image: &String) -> Result<bool, Error> {
+    println!("Downloading image: {}", image);
     let status = Command::new("docker")
-        .current_dir(dockerfile_path_str)
-        .args(docker_args)
+        .arg("pull")
+        .arg(image)
         .status()
         .map_err(|e| Error::CommandError {
-            cmd: format!("docker build -t {} -f {}", image, dockerfile),
+            cmd: format!("docker pull {}", image),
             reason: e.to_string()
         })?;
 
     Ok(status.success())
 }
 
-fn require_named_config(command_name: &str) -> Result<(Configuration, String), Error> {
-    let current_path = std::env::current_dir()
-        .map_err(|e| Error::PathError(format!("Failed to get current directory: {}", e)))?;
+fn tag_image(source: &str, target: &str) -> Result<bool, Error> {
+    let succeeded = docker_command(&["tag", source, target], false)
+        .map_err(|e| Error::CommandError {
+            cmd: format!("docker tag {} {}", source, target),
+            reason: e.to_string()
+        })?;
 
-    // Load config using "any" matcher since up/down/status don't run a specific command
-    let config = load_config(current_path, "any")?;
+    Ok(succeeded)
+}
+
+/// Pulls a published build of the image. A build pushed for the current Dockerfile and build
+/// options (see `contain push`) is preferred; the plain image reference is only used if it
+/// was not built by contain or was built from the same inputs.
+fn pull_image(c: &Configuration) -> Result<bool, Error> {
+    let hash = build_hash(c);
+    let hashed = c.content_hash_image(&hash);
+
+    // Only projects that push can have published a content-hash tag
+    let pulled = if c.has_push_target() && download_image(&hashed)? {
+        hashed
+    } else if download_image(&c.remote_image())? {
+        c.remote_image()
+    } else {
+        return Ok(false);
+    };
+
+    match image_label(&pulled, BUILD_HASH_LABEL)? {
+        Some(label) if label != hash => {
+            println!("{} {} was built from a different Dockerfile or build options, building locally instead",
+                "(info)      ".blue().bold(), pulled);
+            return Ok(false);
+        }
+        _ => {}
+    }
 
-    match config.name.clone() {
-        Some(name) => Ok((config, name)),
-        None => Err(Error::NameRequired { command: command_name.to_string() })
+    if pulled != c.image {
+        return tag_image(&pulled, &c.image);
     }
+
+    Ok(true)
 }
 
-fn container_is_stopped(name: &str) -> Result<bool, Error> {
+/// Lists contain-managed containers. `filters` narrow the list further, e.g. `status=exited` or
+/// a label, and `labels` are the labels callers read, which the CLI has to be asked for.
+fn managed_containers(all: bool, filters: &[String], labels: &[&str]) -> Result<Vec<ContainerSummary>, Error> {
+    let filters: Vec<String> = std::iter::once(format!("label={}=true", MANAGED_LABEL))
+        .chain(filters.iter().cloned())
+        .collect();
+
+    if let Some(found) = engine_list(&format!("/containers/json?all={}", all), &filters) {
+        return found;
+    }
+
+    let mut docker_args = vec!["ps".to_string()];
+    if all {
+        docker_args.push("-a".to_string());
+    }
+    for filter in &filters {
+        docker_args.push("--filter".to_string());
+        docker_args.push(filter.clone());
+    }
+    let label_format: String = labels.iter().map(|label| format!("\t{{{{.Label \"{}\"}}}}", label)).collect();
+    docker_args.push("--format".to_string());
+    docker_args.push(format!("{{{{.Names}}}}\t{{{{.Image}}}}\t{{{{.Status}}}}{}", label_format));
+
     let result = Command::new("docker")
-        .arg("ps")
-        .arg("-a")
-        .arg("-f")
-        .arg(format!("name=^{}$", name))
-        .arg("--format")
-        .arg("{{.Status}}")
+        .args(&docker_args)
         .output()
         .map_err(|e| Error::CommandError {
-            cmd: format!("docker ps -a -f name={}", name),
+            cmd: format!("docker {}", docker_args.join(" ")),
             reason: e.to_string()
         })?;
 
-    let output = String::from_utf8_lossy(&result.stdout)
-        .to_string()
-        .trim()
-        .to_string();
+    if !result.status.success() {
+        return Err(Error::DockerError("Failed to list containers".to_string()));
+    }
 
-    // Container exists but is not running if status starts with "Exited"
-    Ok(!output.is_empty() && output.starts_with("Exited"))
+    Ok(String::from_utf8_lossy(&result.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut fields = line.split('\t').map(String::from);
+            ContainerSummary {
+                names: fields.next().into_iter().collect(),
+                image: fields.next().unwrap_or_default(),
+                status: fields.next().unwrap_or_default(),
+                labels: labels.iter().map(|label| label.to_string()).zip(fields).collect(),
+            }
+        })
+        .collect())
 }
 
-struct ContainerInfo {
-    name: String,
-    status: String,
-    running: bool,
-    image: String,
-    created: String,
-    ports: String,
+/// Whether the named container is running, i.e. commands can be executed inside it.
+fn container_exists(name: &str) -> Result<bool, Error> {
+    Ok(container_state(name)? == Some(ContainerState::Running))
 }
 
-fn get_container_info(name: &str) -> Result<Option<ContainerInfo>, Error> {
-    let result = Command::new("docker")
-        .arg("ps")
-        .arg("-a")
-        .arg("-f")
-        .arg(format!("name=^{}$", name))
-        .arg("--format")
-        .arg("{{.Names}}\t{{.Status}}\t{{.Image}}\t{{.CreatedAt}}\t{{.Ports}}")
-        .output()
+fn host_username() -> String {
+    match get_user_by_uid(get_current_uid()) {
+        None => "dev".to_string(),
+        Some(user) => user.name().to_str().unwrap_or("dev").to_owned()
+    }
+}
+
+fn image_id(image: &str) -> Result<Option<String>, Error> {
+    Ok(inspect_image(image)?.map(|info| info.id))
+}
+
+fn image_label(image: &str, label: &str) -> Result<Option<String>, Error> {
+    Ok(inspect_image(image)?.and_then(|info| info.config.label(label).map(String::from)))
+}
+
+/// Dockerfile for the thin per-user layer. Works on Debian/Ubuntu/Fedora style images
+/// (useradd) as well as Alpine/BusyBox ones (adduser), reusing existing ids if present.
+fn user_layer_dockerfile(base: &str, sudo: bool) -> String {
+    let mut dockerfile = format!(r#"FROM {}
+USER root
+ARG uid
+ARG gid
+ARG username
+RUN set -e; \
+    exists() {{ awk -F: -v key="$2" -v col="$3" '$col == key {{ found = 1 }} END {{ exit !found }}' "$1"; }}; \
+    group=$(awk -F: -v gid="$gid" '$3 == gid {{ print $1; exit }}' /etc/group); \
+    if [ -z "$group" ]; then \
+      group="$username"; \
+      if exists /etc/group "$group" 1; then group="contain$gid"; fi; \
+      if command -v groupadd >/dev/null 2>&1; then groupadd -g "$gid" "$group"; else addgroup -g "$gid" "$group"; fi; \
+    fi; \
+    if ! exists /etc/passwd "$uid" 3; then \
+      user="$username"; \
+      if exists /etc/passwd "$user" 1; then user="contain$uid"; fi; \
+      if command -v useradd >/dev/null 2>&1; then useradd --no-log-init -m -u "$uid" -g "$gid" -s /bin/sh "$user"; \
+      else adduser -D -u "$uid" -G "$group" -s /bin/sh "$user"; fi; \
+    fi
+"#, base);
+
+    if sudo {
+        dockerfile.push_str(r##"RUN set -e; \
+    if command -v apt-get >/dev/null 2>&1; then apt-get update && apt-get install -y --no-install-recommends sudo && rm -rf /var/lib/apt/lists/*; \
+    elif command -v apk >/dev/null 2>&1; then apk add --no-cache sudo; \
+    elif command -v dnf >/dev/null 2>&1; then dnf install -y sudo; \
+    elif command -v yum >/dev/null 2>&1; then yum install -y sudo; fi; \
+    mkdir -p /etc/sudoers.d; \
+    echo "#$uid ALL=(ALL) NOPASSWD: ALL" > /etc/sudoers.d/contain; \
+    chmod 0440 /etc/sudoers.d/contain
+"##);
+    }
+
+    dockerfile
+}
+
+/// Builds the per-user image on top of the configured image unless an up to date one exists.
+/// The derived image records the id of the base it was built from, so rebuilding or
+/// re-pulling the base image causes the user layer to be rebuilt too.
+fn ensure_user_layer(c: &Configuration, options: &GlobalOptions) -> Result<(), Error> {
+    let derived = c.run_image();
+
+    let base = c.user_layer_base();
+
+    let base_id = if options.dry_run {
+        base.clone()
+    } else {
+        let id = image_id(&base)?
+            .ok_or_else(|| Error::DockerError(format!("Base image '{}' not found", base)))?;
+
+        if image_label(&derived, BASE_IMAGE_ID_LABEL)?.as_deref() == Some(id.as_str()) {
+            return Ok(());
+        }
+
+        id
+    };
+
+    let uid_str = format!("uid={}", get_current_uid());
+    let gid_str = format!("gid={}", get_current_gid());
+    let username_str = format!("username={}", host_username());
+    let label_str = format!("{}={}", BASE_IMAGE_ID_LABEL, base_id);
+    let project_str = format!("{}={}", ROOT_PATH_LABEL, c.root_path.display());
+    let image_str = format!("{}={}", IMAGE_LABEL, derived);
+    let built_at_str = format!("{}={}", BUILT_AT_LABEL, unix_now());
+
+    let docker_args = vec![
+        "build",
+        "--build-arg", &uid_str,
+        "--build-arg", &gid_str,
+        "--build-arg", &username_str,
+        "--label", &label_str,
+        "--label", &project_str,
+        "--label", &image_str,
+        "--label", &built_at_str,
+        "-t", &derived,
+        "-",
+    ];
+
+    if options.dry_run {
+        println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&docker_args));
+        return Ok(());
+    }
+
+    println!("Building user layer: {} -> {}", base, derived);
+    println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(&docker_args));
+
+    let mut child = Command::new("docker")
+        .args(&docker_args)
+        .stdin(Stdio::piped())
+        .spawn()
         .map_err(|e| Error::CommandError {
-            cmd: format!("docker ps -a -f name={}", name),
+            cmd: format!("docker build -t {} -", derived),
             reason: e.to_string()
         })?;
 
-    let output = String::from_utf8_lossy(&result.stdout)
-        .to_string()
-        .trim()
-        .to_string();
+    if let Some(mut stdin) = child.stdin.take() {
+        use std::io::Write;
+        stdin.write_all(user_layer_dockerfile(&base, c.user_layer_sudo).as_bytes())
+            .map_err(|e| Error::CommandError {
+                cmd: format!("docker build -t {} -", derived),
+                reason: e.to_string()
+            })?;
+    }
 
-    if output.is_empty() {
-        return Ok(None);
+    let status = child.wait()
+        .map_err(|e| Error::CommandError {
+            cmd: format!("docker build -t {} -", derived),
+            reason: e.to_string()
+        })?;
+
+    if !status.success() {
+        return Err(Error::ImageBuildFailed {
+            image: derived,
+            dockerfile: "<user layer>".to_string()
+        });
     }
 
-    let parts: Vec<&str> = output.split('\t').collect();
-    if parts.len() >= 4 {
-        Ok(Some(ContainerInfo {
-            name: parts[0].to_string(),
-            status: parts[1].to_string(),
-            running: parts[1].starts_with("Up"),
-            image: parts[2].to_string(),
-            created: parts[3].to_string(),
-            ports: if parts.len() >= 5 { parts[4].to_string() } else { String::new() },
-        }))
+    Ok(())
+}
+
+/// Makes sure the image for `c` is available locally: the digest pinned in `.contain.lock`
+/// if there is one, otherwise pulled if possible or built from the configured Dockerfile
+/// (and then recorded in the lock). Finally topped with the per-user layer if enabled.
+fn ensure_image(c: &mut Configuration, options: &GlobalOptions) -> Result<(), Error> {
+    if options.dry_run {
+        // Skip image checks for dry run mode, but show which image is used or how it would be built
+        match &c.pinned {
+            Some(pinned) => {
+                println!("{} {} is pinned to {} by {}", "(dry run)      ".yellow().bold(), c.image, pinned, LOCK_FILENAME);
+            }
+            None => {
+                let docker_args = build_image_args(c, true)?;
+                let args_refs: Vec<&str> = docker_args.iter().map(|s| s.as_str()).collect();
+                if c.has_push_target() {
+                    println!("{} {} is pulled from {} or {} if missing, otherwise built with:", "(dry run)      ".yellow().bold(),
+                        c.image, c.content_hash_image(&build_hash(c)), c.remote_image());
+                } else {
+                    println!("{} {} is pulled from {} if missing, otherwise built with:", "(dry run)      ".yellow().bold(),
+                        c.image, c.remote_image());
+                }
+                println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&args_refs));
+                if let Some(inline) = &c.dockerfile_inline {
+                    for line in inline.lines() {
+                        println!("                  | {}", line);
+                    }
+                }
+            }
+        }
     } else {
-        Ok(None)
+        let stale_pin = ensure_pinned_image(c)?;
+
+        if c.pinned.is_none() {
+            // Check if image exists locally, otherwise try downloading it
+            if !image_exists(&c.image)? && !pull_image(c)? {
+                // Otherwise, build it
+                if !build_image(c)? {
+                    return Err(Error::ImageBuildFailed {
+                        image: c.image.clone(),
+                        dockerfile: c.dockerfile_description()
+                    });
+                }
+            }
+
+            // Pin whatever was pulled or built on first use, but not one-off --image overrides.
+            // Locking is a convenience, a checkout that cannot be written still runs.
+            if !c.image_overridden && (stale_pin || !read_lock(&c.root_path).contains_key(&c.image))
+                && let Some(digest) = image_digest(&c.image)? {
+                match update_lock(&c.root_path, &c.image, &digest) {
+                    Ok(()) => println!("{} Locked {} to {} in {}", "(info)      ".blue().bold(), c.image, digest, LOCK_FILENAME),
+                    Err(e) => eprintln!("{} Could not lock {}: {}", "(warning)   ".yellow().bold(), c.image, e),
+                }
+            }
+        }
     }
+
+    if c.user_layer {
+        ensure_user_layer(c, options)?;
+    }
+
+    println!("{} {}", "(using image)  ".blue().bold(), c.run_image());
+
+    Ok(())
 }
 
-fn container_up(options: GlobalOptions) -> Result<bool, Error> {
-    let (config, name) = require_named_config("up")?;
+/// Makes the pinned image available, pulling it by digest if needed, and warns when the
+/// local tag no longer points to it. A pin to an image id that only exists on the machine
+/// that built it is dropped, so the image is pulled or built from the tag instead; true is
+/// returned then so that the lock is updated to the image actually used.
+fn ensure_pinned_image(c: &mut Configuration) -> Result<bool, Error> {
+    let pinned = match &c.pinned {
+        Some(pinned) => pinned.clone(),
+        None => return Ok(false),
+    };
 
-    let root_path_str = config.root_path.to_str()
-        .ok_or_else(|| Error::PathError("Root path contains invalid UTF-8".to_string()))?;
+    if !image_exists(&pinned)? {
+        if !pinned.contains('@') {
+            println!("{} {} is locked to a local build that no longer exists, using {} instead",
+                "(warning)   ".yellow().bold(), c.image, c.image);
+            c.pinned = None;
+            return Ok(true);
+        }
 
-    println!("{} {}/.contain.yaml", "(configuration)".blue().bold(), root_path_str);
+        if !download_image(&pinned)? {
+            return Err(Error::DockerError(format!(
+                "Failed to pull {} locked in {}, use 'contain lock update' to re-resolve it", pinned, LOCK_FILENAME
+            )));
+        }
+    }
 
-    // Check for passthrough mode
-    if is_inside_container() {
-        return Err(Error::UnsupportedParameters("'contain up' cannot run inside a container".to_string()));
+    if image_exists(&c.image)? {
+        let current = image_digest(&c.image)?;
+        let pinned_id = image_id(&pinned)?;
+        if current.as_deref() != Some(pinned.as_str()) && image_id(&c.image)? != pinned_id {
+            println!("{} {} has moved since it was locked, running the pinned {}", "(warning)   ".yellow().bold(), c.image, pinned);
+            println!("{} Use 'contain lock update' to lock the current image", "(hint)      ".blue().bold());
+        }
+    }
+
+    Ok(false)
+}
+
+/// Resolves an image to a reproducible reference: its registry digest (`repo@sha256:...`)
+/// if it was pulled from or pushed to the image's own repository, otherwise its local image id.
+fn image_digest(image: &str) -> Result<Option<String>, Error> {
+    let info = match inspect_image(image)? {
+        Some(info) => info,
+        None => return Ok(None),
+    };
+
+    let (repository, _) = split_image_reference(image);
+    let digests = info.repo_digests.unwrap_or_default();
+
+    let digest = digests.iter()
+        .find(|d| d.split('@').next() == Some(repository.as_str()));
+
+    Ok(Some(digest.cloned().unwrap_or(info.id)))
+}
+
+/// Reads `.contain.lock` next to the configuration, mapping configured image to pinned reference.
+fn read_lock(root_path: &Path) -> HashMap<String, String> {
+    let mut entries = HashMap::new();
+
+    let lock_path = root_path.join(LOCK_FILENAME);
+    if !lock_path.is_file() {
+        return entries;
     }
 
-    // Check if container already exists and is running
-    if !options.dry_run && container_exists(&name)? {
-        return Err(Error::ContainerAlreadyRunning { name: name.clone() });
+    let lock = config::Config::builder()
+        .add_source(config::File::from(lock_path).format(config::FileFormat::Yaml))
+        .build();
+
+    if let Ok(lock) = lock
+        && let Ok(images) = lock.get_array("images") {
+        for node in images {
+            if let Ok(table) = node.into_table()
+                && let (Some(image), Some(digest)) = (table.get("image"), table.get("digest"))
+                && let (Ok(image), Ok(digest)) = (image.clone().into_string(), digest.clone().into_string()) {
+                entries.insert(image, digest);
+            }
+        }
     }
 
-    // Check if container exists but is stopped - if so, start it
-    if !options.dry_run && container_is_stopped(&name)? {
-        println!("{} Starting stopped container '{}'", "(starting)".green().bold(), &name);
-        return start_stopped_container(&name, &options);
+    entries
+}
+
+fn write_lock(root_path: &Path, entries: &HashMap<String, String>) -> Result<(), Error> {
+    let mut images: Vec<(&String, &String)> = entries.iter().collect();
+    images.sort();
+
+    let mut contents = String::from("# Generated by contain, commit this file to pin image digests.\n");
+    contents.push_str("# Use 'contain lock update' to re-resolve them.\n");
+    contents.push_str("images:\n");
+    for (image, digest) in images {
+        contents.push_str(&format!("  - image: \"{}\"\n    digest: \"{}\"\n", image, digest));
     }
 
-    // Ensure image exists
-    if !options.dry_run {
-        if !image_exists(&config.image)? {
-            if !download_image(&config.image)? {
-                if !build_image(&config.image, &config.dockerfile, &config.root_path, &config.workdir_path, &config.build_args)? {
-                    return Err(Error::ImageBuildFailed {
-                        image: config.image.clone(),
-                        dockerfile: format!("{}/{}", root_path_str, config.dockerfile)
-                    });
+    let lock_path = root_path.join(LOCK_FILENAME);
+    fs::write(&lock_path, contents)
+        .map_err(|e| Error::PathError(format!("Failed to write {}: {}", lock_path.display(), e)))
+}
+
+fn update_lock(root_path: &Path, image: &str, digest: &str) -> Result<(), Error> {
+    let mut entries = read_lock(root_path);
+    entries.insert(image.to_string(), digest.to_string());
+    write_lock(root_path, &entries)
+}
+
+/// Re-resolves every image in the nearest `.contain.yaml` and rewrites `.contain.lock`.
+/// Images are pulled first so the lock follows the registry; images that cannot be
+/// pulled are locked to their local build.
+fn lock_update(options: GlobalOptions) -> Result<bool, Error> {
+    let current_path = env::current_dir()
+        .map_err(|e| Error::PathError(format!("Failed to get current directory: {}", e)))?;
+    let (root_path, _) = find_config_file(current_path.clone(), &options.overrides)?;
+
+    println!("{} {}", "(configuration)".blue().bold(), config_file_path(&root_path, &options.overrides).display());
+
+    let mut entries = read_lock(&root_path);
+
+    // Locked under the image as resolved, with templates and the profile applied, which is
+    // what pinned images are looked up by
+    for c in load_all_configs(current_path, &options.overrides)? {
+        if c.image_overridden {
+            println!("{} {} is given with --image, which is never locked", "(info)      ".blue().bold(), c.image);
+            continue;
+        }
+
+        let (image, remote) = (c.image.clone(), c.remote_image());
+
+        if options.dry_run {
+            println!("{} docker pull {}", "(dry run)      ".yellow().bold(), remote);
+            continue;
+        }
+
+        if download_image(&remote)? && remote != image {
+            tag_image(&remote, &image)?;
+        }
+
+        match image_digest(&remote)?.or(image_digest(&image)?) {
+            Some(digest) => {
+                if entries.get(&image) != Some(&digest) {
+                    println!("{} {} -> {}", "(locked)    ".green().bold(), image, digest);
                 }
+                entries.insert(image, digest);
             }
+            None => println!("{} {} is neither pullable nor built locally, skipping it", "(warning)   ".yellow().bold(), image),
         }
     }
 
-    println!("{} {}", "(using image)  ".blue().bold(), config.image);
+    if options.dry_run {
+        return Ok(true);
+    }
+
+    write_lock(&root_path, &entries)?;
+    println!("{} Updated {}", "(success)".green().bold(), root_path.join(LOCK_FILENAME).display());
 
-    // Start container in detached mode
-    docker_run_detached(&config, &name, &options)
+    Ok(true)
 }
 
-fn docker_run_detached(c: &Configuration, name: &str, options: &GlobalOptions) -> Result<bool, Error> {
-    let uid = get_current_uid();
-    let gid = get_current_gid();
-    let uid_gid = format!("{}:{}", uid, gid);
+/// Returns the driver of the given (or currently selected) buildx builder, None if it cannot be determined.
+fn buildx_driver(builder: Option<&str>) -> Option<String> {
+    let mut args = vec!["buildx", "inspect"];
+    if let Some(builder) = builder {
+        args.push(builder);
+    }
 
-    let mount = format!("type=bind,src={},dst={}", c.root_path.to_str().unwrap(), c.workdir_path);
+    let output = Command::new("docker").args(&args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
 
-    let mut docker_args: Vec<String> = vec!["run".to_string()];
+    String::from_utf8_lossy(&output.stdout).lines()
+        .find_map(|line| line.trim().strip_prefix("Driver:").map(|driver| driver.trim().to_string()))
+}
 
-    // Always detached for 'up'
-    docker_args.push("-d".to_string());
+/// Arguments for `docker build`, run from the config root. An inline Dockerfile is read from stdin.
+/// A dry run does not ask buildx for the builder's driver and shows the cache export as configured.
+fn build_image_args(c: &Configuration, dry_run: bool) -> Result<Vec<String>, Error> {
+    let context_path = match &c.build_context {
+        Some(context) => c.root_path.join(context),
+        None => c.root_path.clone(),
+    };
+    let context_path_str = context_path.to_str()
+        .ok_or_else(|| Error::PathError("Build context path contains invalid UTF-8".to_string()))?;
+
+    let mut docker_args: Vec<String> = if c.buildx {
+        // --load puts the result in the local image store like a plain docker build
+        let mut args = vec!["buildx".to_string(), "build".to_string(), "--load".to_string()];
+        if let Some(builder) = &c.buildx_builder {
+            args.push("--builder".to_string());
+            args.push(builder.clone());
+        }
+        args
+    } else {
+        vec!["build".to_string()]
+    };
 
-    // Always use the container name
-    docker_args.push("--name".to_string());
-    docker_args.push(name.to_string());
+    docker_args.push("--build-arg".to_string());
+    docker_args.push(format!("uid={}", get_current_uid()));
+    docker_args.push("--build-arg".to_string());
+    docker_args.push(format!("gid={}", get_current_gid()));
+    docker_args.push("--build-arg".to_string());
+    docker_args.push(format!("username={}", host_username()));
+    docker_args.push("--build-arg".to_string());
+    docker_args.push(format!("workdir_path={}", c.workdir_path));
+
+    for item in &c.build_args {
+        docker_args.push("--build-arg".to_string());
+        docker_args.push(item.trim().to_string());
+    }
 
-    // User mapping (unless root flag)
-    if !options.run_as_root && !c.flags.contains(&"root".to_string()) {
-        docker_args.push("-u".to_string());
-        docker_args.push(uid_gid);
+    if let Some(target) = &c.build_target {
+        docker_args.push("--target".to_string());
+        docker_args.push(target.clone());
     }
 
-    // Working directory
-    docker_args.push("-w".to_string());
-    docker_args.push(c.workdir_path.clone());
+    for item in &c.cache_from {
+        docker_args.push("--cache-from".to_string());
+        docker_args.push(item.clone());
+    }
 
-    // Environment variables
-    let all_env_variables = [&c.env_variables[..], &options.cli_env_variables[..]].concat();
-    for item in &all_env_variables {
-        docker_args.push("-e".to_string());
-        docker_args.push(item.trim().to_string());
+    for item in &c.build_secrets {
+        docker_args.push("--secret".to_string());
+        docker_args.push(item.clone());
     }
 
-    // Mount workspace
-    docker_args.push("--mount".to_string());
-    docker_args.push(mount);
+    if let Some(platform) = &c.platform {
+        docker_args.push("--platform".to_string());
+        docker_args.push(platform.clone());
+    }
+
+    for item in &c.build_ssh {
+        docker_args.push("--ssh".to_string());
+        docker_args.push(item.clone());
+    }
+
+    // The default 'docker' driver can import a cache but not export one
+    let wants_cache_export = !c.cache_to.is_empty() || c.cache_dir.is_some();
+    let cache_export = wants_cache_export
+        && (dry_run || buildx_driver(c.buildx_builder.as_deref()).as_deref() != Some("docker"));
+    if wants_cache_export && !cache_export {
+        eprintln!("{} The '{}' buildx builder uses the docker driver, which cannot export a build cache. Set 'buildx_builder' to a docker-container builder to enable cache_to/cache_dir",
+            "(warning)   ".yellow().bold(), c.buildx_builder.as_deref().unwrap_or("default"));
+    }
+
+    if cache_export {
+        for item in &c.cache_to {
+            docker_args.push("--cache-to".to_string());
+            docker_args.push(item.clone());
+        }
+    }
+
+    // A local directory used both as cache source and destination
+    if let Some(cache_dir) = &c.cache_dir {
+        let cache_path = c.root_path.join(cache_dir);
+        docker_args.push("--cache-from".to_string());
+        docker_args.push(format!("type=local,src={}", cache_path.display()));
+        if cache_export {
+            docker_args.push("--cache-to".to_string());
+            docker_args.push(format!("type=local,dest={},mode=max", cache_path.display()));
+        }
+    }
+
+    if let Some(progress) = &c.build_progress {
+        docker_args.push("--progress".to_string());
+        docker_args.push(progress.clone());
+    }
+
+    // Record where the image came from so 'contain images' can find and prune it
+    let hash = build_hash(c);
+    docker_args.push("--label".to_string());
+    docker_args.push(format!("{}={}", ROOT_PATH_LABEL, c.root_path.display()));
+    docker_args.push("--label".to_string());
+    docker_args.push(format!("{}={}", IMAGE_LABEL, c.image));
+    docker_args.push("--label".to_string());
+    docker_args.push(format!("{}={}", BUILD_HASH_LABEL, hash));
+    docker_args.push("--label".to_string());
+    docker_args.push(format!("{}={}", BUILT_AT_LABEL, unix_now()));
+
+    docker_args.push("-t".to_string());
+    docker_args.push(c.image.clone());
+
+    if c.tag_content_hash {
+        let (repository, _) = split_image_reference(&c.image);
+        docker_args.push("-t".to_string());
+        docker_args.push(format!("{}:contain-{}", repository, &hash[..12]));
+    }
+
+    docker_args.push("-f".to_string());
+    docker_args.push(c.dockerfile.clone().unwrap_or_else(|| "-".to_string()));
+    docker_args.push(context_path_str.to_string());
+
+    Ok(docker_args)
+}
+
+fn build_image(c: &Configuration) -> Result<bool, Error> {
+    let root_path_str = c.root_path.to_str()
+        .ok_or_else(|| Error::PathError("Root path contains invalid UTF-8".to_string()))?;
+
+    println!("Building image: {} -> {}", c.dockerfile_description(), c.image);
+
+    let docker_args = build_image_args(c, false)?;
+    let args_refs: Vec<&str> = docker_args.iter().map(|s| s.as_str()).collect();
+
+    println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(&args_refs));
+
+    let mut command = Command::new("docker");
+    command.current_dir(root_path_str).args(&args_refs);
+
+    // Build secrets and SSH forwarding are BuildKit features
+    if !c.build_secrets.is_empty() || !c.build_ssh.is_empty() {
+        command.env("DOCKER_BUILDKIT", "1");
+    }
+
+    if c.dockerfile_inline.is_some() {
+        command.stdin(Stdio::piped());
+    }
+
+    let build_error = |e: std::io::Error| Error::CommandError {
+        cmd: format!("docker build -t {} -f {}", c.image, c.dockerfile_description()),
+        reason: e.to_string()
+    };
+
+    let mut child = command.spawn().map_err(build_error)?;
+
+    if let (Some(inline), Some(mut stdin)) = (&c.dockerfile_inline, child.stdin.take()) {
+        use std::io::Write;
+        stdin.write_all(inline.as_bytes()).map_err(build_error)?;
+    }
+
+    let status = child.wait().map_err(build_error)?;
+
+    Ok(status.success())
+}
+
+fn require_named_config(command_name: &str, options: &GlobalOptions) -> Result<(Configuration, String), Error> {
+    let current_path = std::env::current_dir()
+        .map_err(|e| Error::PathError(format!("Failed to get current directory: {}", e)))?;
+
+    // Load config using "any" matcher since up/down/status don't run a specific command
+    let mut config = load_config(current_path, "any", &options.overrides)?;
+
+    let name = match config.name.clone() {
+        Some(name) if options.dry_run => name,
+        Some(name) => resolve_container_name(&config, &name)?.0,
+        None => return Err(Error::NameRequired { command: command_name.to_string() })
+    };
+
+    config.name = Some(name.clone());
+    Ok((config, name))
+}
+
+/// Picks the container name for this checkout. When the configured name is taken by a
+/// container of another checkout (a git worktree or a second clone), a suffix derived from
+/// the project root is added, so both can have a background container at the same time.
+/// The container of the picked name is returned too, if it exists, so callers need not inspect it again.
+fn resolve_container_name(c: &Configuration, name: &str) -> Result<(String, Option<ContainerInspect>), Error> {
+    let root = c.root_path.to_string_lossy();
+    let suffixed = format!("{}-{}", name, &fnv1a(&root)[..8]);
+    let exact = |name: &str| -> Result<Option<ContainerInspect>, Error> {
+        Ok(inspect_container(name)?.filter(|info| info.name.trim_start_matches('/') == name))
+    };
+
+    if let Some(info) = exact(&suffixed)? {
+        return Ok((suffixed, Some(info)));
+    }
+
+    let info = exact(name)?;
+    match info.as_ref().and_then(|info| info.config.label(ROOT_PATH_LABEL)) {
+        Some(other) if !other.is_empty() && other != root => {
+            println!("{} '{}' is used by {}, using '{}' for this checkout", "(info)      ".blue().bold(), name, other, suffixed);
+            Ok((suffixed, None))
+        }
+        _ => Ok((name.to_string(), info)),
+    }
+}
+
+/// Makes sure a running container bind-mounts this project at the workdir before
+/// commands are executed in it, so they never run against another checkout.
+fn check_workspace_mount(c: &Configuration, name: &str, info: &ContainerInspect) -> Result<(), Error> {
+    let is_project = |source: &str| Path::new(source) == c.root_path
+        || fs::canonicalize(source).ok().zip(fs::canonicalize(&c.root_path).ok()).is_some_and(|(a, b)| a == b);
+
+    let mounted = info.mounts.iter()
+        .find(|mount| mount.destination == c.workdir_path)
+        .map(|mount| mount.source.clone())
+        .unwrap_or_default();
+
+    if is_project(&mounted) {
+        return Ok(());
+    }
+
+    // The right checkout, only mounted elsewhere, e.g. because of --workdir
+    if let Some(mount) = info.mounts.iter().find(|mount| is_project(&mount.source)) {
+        return Err(Error::WorkdirMismatch {
+            name: name.to_string(),
+            mounted_at: mount.destination.clone(),
+            workdir: c.workdir_path.clone(),
+        });
+    }
+
+    Err(Error::WorkspaceMismatch {
+        name: name.to_string(),
+        mounted: if mounted.is_empty() { "nothing".to_string() } else { mounted },
+        root: c.root_path.display().to_string(),
+    })
+}
+
+/// Lifecycle state of a container, as reported by docker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ContainerState {
+    Created,
+    Running,
+    Paused,
+    Restarting,
+    Exited,
+    // A container being removed is as unusable as a dead one
+    #[serde(alias = "removing")]
+    Dead,
+    // Any state a newer docker may add, rather than failing to read the container at all
+    #[serde(other)]
+    Unknown,
+}
+
+impl ContainerState {
+    /// Created or exited: the container can be started again as it is.
+    fn is_stopped(self) -> bool {
+        matches!(self, ContainerState::Created | ContainerState::Exited)
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            ContainerState::Created => "created",
+            ContainerState::Running => "running",
+            ContainerState::Paused => "paused",
+            ContainerState::Restarting => "restarting",
+            ContainerState::Exited => "exited",
+            ContainerState::Dead => "dead",
+            ContainerState::Unknown => "unknown",
+        }
+    }
+}
+
+/// The parts of `docker container inspect` contain relies on.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerInspect {
+    id: String,
+    name: String,
+    created: String,
+    state: ContainerStatus,
+    #[serde(default, deserialize_with = "null_as_default")]
+    config: InspectConfig,
+    #[serde(default, deserialize_with = "null_as_default")]
+    mounts: Vec<InspectMount>,
+    network_settings: Option<InspectNetworkSettings>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerStatus {
+    status: ContainerState,
+    exit_code: i64,
+    started_at: String,
+    finished_at: String,
+    health: Option<InspectHealth>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectHealth {
+    status: String,
+    #[serde(default)]
+    failing_streak: u64,
+}
+
+/// `Config` of a container or an image.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectConfig {
+    #[serde(default)]
+    image: String,
+    #[serde(default)]
+    tty: bool,
+    #[serde(default, deserialize_with = "null_as_default")]
+    labels: HashMap<String, String>,
+}
+
+impl InspectConfig {
+    fn label(&self, label: &str) -> Option<&str> {
+        self.labels
+            .get(label)
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectMount {
+    source: String,
+    destination: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectNetworkSettings {
+    ports: Option<HashMap<String, Option<Vec<InspectPortBinding>>>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectPortBinding {
+    host_ip: String,
+    host_port: String,
+}
+
+impl ContainerInspect {
+    /// Published ports in `docker ps` notation, e.g. `0.0.0.0:8080->80/tcp`.
+    fn published_ports(&self) -> Vec<String> {
+        let mut ports: Vec<String> = self.network_settings.iter()
+            .flat_map(|settings| settings.ports.iter().flatten())
+            .flat_map(|(port, bindings)| bindings.iter().flatten()
+                .map(move |binding| format!("{}:{}->{}", binding.host_ip, binding.host_port, port)))
+            .collect();
+        ports.sort();
+        ports
+    }
+}
+
+/// The parts of `docker image inspect` contain relies on.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ImageInspect {
+    id: String,
+    repo_tags: Option<Vec<String>>,
+    repo_digests: Option<Vec<String>>,
+    #[serde(default)]
+    size: u64,
+    #[serde(default, deserialize_with = "null_as_default")]
+    config: InspectConfig,
+}
+
+/// A container as listed by `docker ps`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerSummary {
+    names: Vec<String>,
+    image: String,
+    // Human readable, e.g. `Up 2 hours` or `Exited (0) 3 days ago`
+    status: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    labels: HashMap<String, String>,
+}
+
+impl ContainerSummary {
+    fn name(&self) -> &str {
+        self.names.first().map(|name| name.trim_start_matches('/')).unwrap_or_default()
+    }
+
+    /// The value of `label`, empty if it is not set.
+    fn label(&self, label: &str) -> &str {
+        self.labels.get(label).map(String::as_str).unwrap_or_default()
+    }
+}
+
+/// An image as listed by the Engine API.
+#[derive(Debug, Deserialize)]
+struct ImageSummary {
+    #[serde(rename = "Id")]
+    id: String,
+}
+
+/// A volume or network as listed by the Engine API.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ResourceSummary {
+    name: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    labels: HashMap<String, String>,
+}
+
+/// The Engine API lists volumes wrapped in an object, next to any warnings.
+#[derive(Debug, Deserialize)]
+struct VolumeList {
+    #[serde(rename = "Volumes", default, deserialize_with = "null_as_default")]
+    volumes: Vec<ResourceSummary>,
+}
+
+/// docker writes `null` rather than an empty list or object in places.
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Where the Docker Engine API listens, if it can be reached over a Unix socket: DOCKER_HOST,
+/// else the endpoint of DOCKER_CONTEXT or the current context, like the docker CLI. None for
+/// TCP and SSH hosts, which are left to the CLI.
+fn engine_socket() -> Option<&'static Path> {
+    static SOCKET: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+    SOCKET.get_or_init(|| {
+        if env::var("CONTAIN_DOCKER_CLI").is_ok_and(|value| value == "1") {
+            return None;
+        }
+
+        let host = match env::var("DOCKER_HOST") {
+            Ok(host) if !host.is_empty() => host,
+            _ => docker_context_host()?,
+        };
+
+        host.strip_prefix("unix://").map(PathBuf::from)
+    }).as_deref()
+}
+
+/// The docker endpoint of DOCKER_CONTEXT, or of the context selected with `docker context use`.
+fn docker_context_host() -> Option<String> {
+    let config_dir = match env::var("DOCKER_CONFIG") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var("HOME").ok()?).join(".docker"),
+    };
+
+    let context = match env::var("DOCKER_CONTEXT") {
+        Ok(context) if !context.is_empty() => context,
+        _ => fs::read_to_string(config_dir.join("config.json")).ok()
+            .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+            .and_then(|config| config.get("currentContext")?.as_str().map(String::from))
+            .unwrap_or_else(|| "default".to_string()),
+    };
+
+    if context == "default" {
+        return Some(DEFAULT_DOCKER_HOST.to_string());
+    }
+
+    // Context directories are named after a digest of the context name, so look through all of them
+    fs::read_dir(config_dir.join("contexts/meta")).ok()?
+        .flatten()
+        .filter_map(|entry| fs::read_to_string(entry.path().join("meta.json")).ok())
+        .filter_map(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+        .find(|meta| meta.get("Name").and_then(|name| name.as_str()) == Some(context.as_str()))
+        .and_then(|meta| meta.pointer("/Endpoints/docker/Host")?.as_str().map(String::from))
+}
+
+/// The head of a Docker Engine API response, with the body left on the connection.
+struct EngineResponse {
+    status: u16,
+    chunked: bool,
+    stream: UnixStream,
+}
+
+impl EngineResponse {
+    /// The body as it arrives, with the chunked transfer encoding removed.
+    fn reader(self) -> Box<dyn Read> {
+        if self.chunked {
+            Box::new(ChunkedReader { inner: BufReader::new(self.stream), remaining: 0, done: false })
+        } else {
+            Box::new(self.stream)
+        }
+    }
+
+    /// Reads the whole body, which ends when the daemon closes the connection.
+    fn body(self) -> Option<Vec<u8>> {
+        let mut body = Vec::new();
+        self.reader().read_to_end(&mut body).ok()?;
+        Some(body)
+    }
+}
+
+/// Sends a request to the Docker Engine API and reads the head of the response. None if the
+/// API cannot be reached, in which case callers fall back to the docker CLI. With `upgrade`
+/// the daemon hands the connection over to the streams of a container or exec instance.
+fn engine_send(method: &str, path: &str, body: Option<&serde_json::Value>, upgrade: bool) -> Option<EngineResponse> {
+    let mut stream = UnixStream::connect(engine_socket()?).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(30))).ok()?;
+
+    let body = body.map(|body| body.to_string()).unwrap_or_default();
+    let connection = if upgrade { "Upgrade\r\nUpgrade: tcp" } else { "close" };
+    write!(stream, "{} {} HTTP/1.1\r\nHost: docker\r\nConnection: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        method, path, connection, body.len(), body).ok()?;
+
+    // Read the head a byte at a time, so none of what follows it is consumed
+    let mut head = Vec::new();
+    let mut byte = [0; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if stream.read(&mut byte).ok()? == 0 || head.len() > 65536 {
+            return None;
+        }
+        head.push(byte[0]);
+    }
+
+    let head = String::from_utf8_lossy(&head).to_ascii_lowercase();
+    let mut lines = head.lines();
+    let status = lines.next()?.split_whitespace().nth(1)?.parse().ok()?;
+    let chunked = lines.any(|line| line.starts_with("transfer-encoding:") && line.contains("chunked"));
+
+    Some(EngineResponse { status, chunked, stream })
+}
+
+/// Sends a request to the Docker Engine API and returns the status code and body. None if
+/// the API cannot be reached.
+fn engine_request(method: &str, path: &str, body: Option<&serde_json::Value>) -> Option<(u16, Vec<u8>)> {
+    let response = engine_send(method, path, body, false)?;
+    let status = response.status;
+    Some((status, response.body()?))
+}
+
+/// The reason the daemon gives for a failed request.
+fn engine_error(body: &[u8]) -> String {
+    serde_json::from_slice::<serde_json::Value>(body).ok()
+        .and_then(|error| error.get("message")?.as_str().map(String::from))
+        .unwrap_or_else(|| String::from_utf8_lossy(body).trim().to_string())
+}
+
+/// Removes the chunked transfer encoding from a response body as it is read.
+struct ChunkedReader<R> {
+    inner: R,
+    remaining: usize,
+    done: bool,
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.remaining == 0 {
+            if self.done {
+                return Ok(0);
+            }
+
+            // Each chunk after the first follows the line break that ends the previous one
+            let mut line = String::new();
+            while line.trim().is_empty() {
+                if self.inner.read_line(&mut line)? == 0 {
+                    return Ok(0);
+                }
+            }
+
+            let size = line.trim().split(';').next().unwrap_or_default();
+            self.remaining = usize::from_str_radix(size, 16)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            if self.remaining == 0 {
+                self.done = true;
+                return Ok(0);
+            }
+        }
+
+        let len = buf.len().min(self.remaining);
+        let read = self.inner.read(&mut buf[..len])?;
+        self.remaining -= read;
+        Ok(read)
+    }
+}
+
+/// Escapes a container name, image reference or query value for use in an Engine API path.
+/// Slashes, colons and `@` are part of image references and are kept.
+fn encode_path_segment(segment: &str) -> String {
+    segment.bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' | b':' | b'@' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Inspects the given containers or images (`kind`) through the Engine API. None if the API
+/// cannot be reached or answers unexpectedly, so the CLI can be tried instead.
+fn engine_inspect<T: DeserializeOwned>(kind: &str, references: &[&str]) -> Option<Result<Vec<T>, Error>> {
+    let mut found = Vec::new();
+
+    for reference in references {
+        let (status, body) = engine_request("GET", &format!("/{}s/{}/json", kind, encode_path_segment(reference)), None)?;
+
+        match status {
+            200 => match serde_json::from_slice(&body) {
+                Ok(info) => found.push(info),
+                Err(e) => return Some(Err(Error::DockerError(format!("Unexpected response from the Docker Engine API: {}", e)))),
+            },
+            404 => continue,
+            _ => return None,
+        }
+    }
+
+    Some(Ok(found))
+}
+
+/// Lists the containers, images, volumes or networks at `path` that match `filters` (`docker ps`
+/// notation, e.g. `label=a=b`) through the Engine API. None if the API cannot be reached or
+/// answers unexpectedly.
+fn engine_list<T: DeserializeOwned>(path: &str, filters: &[String]) -> Option<Result<T, Error>> {
+    let mut by_kind: HashMap<&str, Vec<&str>> = HashMap::new();
+    for filter in filters {
+        let (kind, value) = filter.split_once('=')?;
+        by_kind.entry(kind).or_default().push(value);
+    }
+
+    let filters = serde_json::to_string(&by_kind).ok()?;
+    let separator = if path.contains('?') { '&' } else { '?' };
+    let (status, body) = engine_request("GET", &format!("{}{}filters={}", path, separator, encode_path_segment(&filters)), None)?;
+
+    match status {
+        200 => Some(serde_json::from_slice(&body)
+            .map_err(|e| Error::DockerError(format!("Unexpected response from the Docker Engine API: {}", e)))),
+        _ => None,
+    }
+}
+
+/// Makes the change a `docker start`, `stop`, `restart`, `unpause`, `rm`, `tag`, `volume create`
+/// or `volume`, `network` and `image rm` with these arguments would, through the Engine API.
+/// None if the API cannot be reached or the command is not one of these, otherwise the reason
+/// the daemon gives if it fails.
+fn engine_command(args: &[&str]) -> Option<Result<(), String>> {
+    let (method, path, body) = match *args {
+        [action @ ("start" | "stop" | "restart" | "unpause"), name] =>
+            ("POST", format!("/containers/{}/{}", encode_path_segment(name), action), None),
+        ["rm", name] => ("DELETE", format!("/containers/{}", encode_path_segment(name)), None),
+        ["rm", "-f", name] => ("DELETE", format!("/containers/{}?force=true", encode_path_segment(name)), None),
+        [kind @ ("volume" | "network" | "image"), "rm", name] =>
+            ("DELETE", format!("/{}s/{}", kind, encode_path_segment(name)), None),
+        ["volume", "create", "--label", label, name] => {
+            let (key, value) = label.split_once('=')?;
+            ("POST", "/volumes/create".to_string(), Some(serde_json::json!({ "Name": name, "Labels": { key: value } })))
+        }
+        ["tag", source, target] if !target.contains('@') => {
+            let (repository, tag) = split_image_reference(target);
+            ("POST", format!("/images/{}/tag?repo={}&tag={}", encode_path_segment(source),
+                encode_path_segment(&repository), encode_path_segment(&tag)), None)
+        }
+        _ => return None,
+    };
+
+    let (status, body) = engine_request(method, &path, body.as_ref())?;
+
+    match status {
+        // 304 when the container already is in the requested state
+        200..=299 | 304 => Some(Ok(())),
+        _ => Some(Err(engine_error(&body))),
+    }
+}
+
+/// Runs a docker command that changes a container, volume, network or image, through the
+/// Engine API when it can be reached (see `engine_command`) and the docker CLI otherwise.
+/// Failures are reported on stderr like the CLI does, unless `quiet`. Returns whether the
+/// command succeeded.
+fn docker_command(args: &[&str], quiet: bool) -> std::io::Result<bool> {
+    match engine_command(args) {
+        Some(Ok(())) => Ok(true),
+        Some(Err(reason)) => {
+            if !quiet {
+                eprintln!("Error response from daemon: {}", reason);
+            }
+            Ok(false)
+        }
+        None => Command::new("docker")
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(if quiet { Stdio::null() } else { Stdio::inherit() })
+            .status()
+            .map(|status| status.success()),
+    }
+}
+
+/// `docker run` and `docker exec` arguments as contain builds them, read back so the same
+/// container or command can be set up through the Engine API.
+#[derive(Debug, Default)]
+struct RunArgs {
+    name: Option<String>,
+    labels: HashMap<String, String>,
+    user: String,
+    workdir: String,
+    env: Vec<String>,
+    mounts: Vec<serde_json::Value>,
+    ports: HashMap<String, Vec<serde_json::Value>>,
+    privileged: bool,
+    platform: Option<String>,
+    restart: Option<serde_json::Value>,
+    auto_remove: bool,
+    interactive: bool,
+    detach: bool,
+    // The image to run, or the container to execute in
+    target: String,
+    command: Vec<String>,
+}
+
+impl RunArgs {
+    /// Reads the options contain passes to `docker run` or `docker exec`, the subcommand left
+    /// out. None for options contain does not use, and for mounts and ports the API would need
+    /// spelled out differently, which are left to the CLI.
+    fn parse(args: &[&str]) -> Option<RunArgs> {
+        let mut run = RunArgs::default();
+        let mut args = args.iter();
+
+        while let Some(&arg) = args.next() {
+            match arg {
+                "--name" => run.name = Some(args.next()?.to_string()),
+                "--label" => {
+                    let label = args.next()?;
+                    let (key, value) = label.split_once('=').unwrap_or((label, ""));
+                    run.labels.insert(key.to_string(), value.to_string());
+                }
+                "-u" => run.user = args.next()?.to_string(),
+                "-w" => run.workdir = args.next()?.to_string(),
+                "-e" => {
+                    // Like the CLI, a variable without a value is taken from this environment if set
+                    let variable = args.next()?;
+                    if variable.contains('=') {
+                        run.env.push(variable.to_string());
+                    } else if let Ok(value) = env::var(variable) {
+                        run.env.push(format!("{}={}", variable, value));
+                    }
+                }
+                "--mount" => run.mounts.push(parse_mount(args.next()?)?),
+                "-p" => {
+                    let (port, binding) = parse_port(args.next()?)?;
+                    run.ports.entry(port).or_default().push(binding);
+                }
+                "--privileged" => run.privileged = true,
+                "--platform" => run.platform = Some(args.next()?.to_string()),
+                "--restart" => {
+                    let restart = args.next()?;
+                    let (name, retries) = restart.split_once(':').unwrap_or((restart, "0"));
+                    run.restart = Some(serde_json::json!({ "Name": name, "MaximumRetryCount": retries.parse::<u64>().ok()? }));
+                }
+                "--rm" => run.auto_remove = true,
+                "-it" => run.interactive = true,
+                "-d" => run.detach = true,
+                _ if arg.starts_with('-') => return None,
+                _ => {
+                    run.target = arg.to_string();
+                    run.command = args.map(|arg| arg.to_string()).collect();
+                    return Some(run);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// The body of a `POST /containers/create` request.
+    fn create_body(&self) -> serde_json::Value {
+        let exposed: HashMap<&String, serde_json::Value> = self.ports.keys()
+            .map(|port| (port, serde_json::json!({})))
+            .collect();
+
+        let mut body = serde_json::json!({
+            "Image": self.target,
+            "User": self.user,
+            "WorkingDir": self.workdir,
+            "Env": self.env,
+            "Labels": self.labels,
+            "Tty": self.interactive,
+            "OpenStdin": self.interactive,
+            "StdinOnce": self.interactive,
+            "AttachStdin": self.interactive,
+            "AttachStdout": !self.detach,
+            "AttachStderr": !self.detach,
+            "ExposedPorts": exposed,
+            "HostConfig": {
+                "Mounts": self.mounts,
+                "PortBindings": self.ports,
+                "Privileged": self.privileged,
+                "AutoRemove": self.auto_remove,
+            },
+        });
+
+        // Without a command the image's own is run
+        if !self.command.is_empty() {
+            body["Cmd"] = serde_json::json!(self.command);
+        }
+        if let Some(restart) = &self.restart {
+            body["HostConfig"]["RestartPolicy"] = restart.clone();
+        }
+
+        body
+    }
+
+    /// The body of a `POST /containers/{id}/exec` request.
+    fn exec_body(&self) -> serde_json::Value {
+        serde_json::json!({
+            "AttachStdin": self.interactive,
+            "AttachStdout": true,
+            "AttachStderr": true,
+            "Tty": self.interactive,
+            "User": self.user,
+            "WorkingDir": self.workdir,
+            "Env": self.env,
+            "Cmd": self.command,
+        })
+    }
+}
+
+/// A `--mount` option as the Engine API takes it. None for settings contain does not use, such
+/// as volume options or bind propagation, and for relative bind sources, which the CLI resolves.
+fn parse_mount(mount: &str) -> Option<serde_json::Value> {
+    let (mut kind, mut source, mut target, mut read_only) = ("volume", "", "", false);
+
+    for field in mount.split(',') {
+        match field.split_once('=').unwrap_or((field, "true")) {
+            ("type", value) => kind = value,
+            ("source" | "src", value) => source = value,
+            ("target" | "destination" | "dst", value) => target = value,
+            ("readonly" | "ro", value) => read_only = matches!(value, "true" | "1"),
+            _ => return None,
+        }
+    }
+
+    if kind == "bind" && !source.starts_with('/') {
+        return None;
+    }
+
+    Some(serde_json::json!({ "Type": kind, "Source": source, "Target": target, "ReadOnly": read_only }))
+}
+
+/// A `-p` option as the container port and host binding the Engine API takes. None for port
+/// ranges and IPv6 addresses, which are left to the CLI.
+fn parse_port(port: &str) -> Option<(String, serde_json::Value)> {
+    if port.contains(['-', '[']) {
+        return None;
+    }
+
+    let (ports, protocol) = port.split_once('/').unwrap_or((port, "tcp"));
+    let (ip, host, container) = match ports.split(':').collect::<Vec<_>>()[..] {
+        [container] => ("", "", container),
+        [host, container] => ("", host, container),
+        [ip, host, container] => (ip, host, container),
+        _ => return None,
+    };
+
+    Some((format!("{}/{}", container, protocol), serde_json::json!({ "HostIp": ip, "HostPort": host })))
+}
+
+fn engine_unreachable() -> Error {
+    Error::DockerError("Lost the connection to the Docker Engine API".to_string())
+}
+
+/// The id of a container or exec instance the Engine API just created.
+fn created_id(body: &[u8]) -> Result<String, Error> {
+    serde_json::from_slice::<serde_json::Value>(body).ok()
+        .and_then(|created| created.get("Id")?.as_str().map(String::from))
+        .ok_or_else(|| Error::DockerError("Unexpected response from the Docker Engine API: no id".to_string()))
+}
+
+fn engine_start(path: &str) -> Result<(), Error> {
+    let (status, body) = engine_request("POST", &format!("{}/start", path), None).ok_or_else(engine_unreachable)?;
+
+    match status {
+        204 | 304 => Ok(()),
+        _ => Err(Error::DockerError(format!("Failed to start container: {}", engine_error(&body)))),
+    }
+}
+
+/// Takes over the connection of a `POST` to `path` for the streams of a container or exec instance.
+fn engine_hijack(path: &str, body: Option<&serde_json::Value>) -> Result<UnixStream, Error> {
+    let response = engine_send("POST", path, body, true).ok_or_else(engine_unreachable)?;
+
+    match response.status {
+        101 | 200 => {
+            // The streams stay open for as long as the container or command runs
+            let _ = response.stream.set_read_timeout(None);
+            Ok(response.stream)
+        }
+        _ => Err(Error::DockerError(engine_error(&response.body().unwrap_or_default()))),
+    }
+}
+
+/// Raw mode for this terminal while it is attached to a container's TTY, so keys reach the
+/// container as typed. The previous settings are restored when dropped.
+struct RawTerminal(Option<libc::termios>);
+
+impl RawTerminal {
+    fn enable(tty: bool) -> RawTerminal {
+        // SAFETY: termios is plain data, filled in by tcgetattr before it is used
+        unsafe {
+            let mut settings: libc::termios = std::mem::zeroed();
+            if !tty || libc::isatty(libc::STDIN_FILENO) != 1 || libc::tcgetattr(libc::STDIN_FILENO, &mut settings) != 0 {
+                return RawTerminal(None);
+            }
+
+            let original = settings;
+            libc::cfmakeraw(&mut settings);
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &settings);
+            RawTerminal(Some(original))
+        }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        if let Some(original) = &self.0 {
+            // SAFETY: restores settings read by tcgetattr
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original) };
+        }
+    }
+}
+
+/// The size of this terminal as (rows, columns), if stdout is one.
+fn terminal_size() -> Option<(u16, u16)> {
+    // SAFETY: winsize is plain data, filled in by the ioctl
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 || size.ws_row == 0 {
+        return None;
+    }
+
+    Some((size.ws_row, size.ws_col))
+}
+
+static RECEIVED_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn record_signal(signal: libc::c_int) {
+    RECEIVED_SIGNAL.store(signal, Ordering::SeqCst);
+}
+
+/// Passes on what happens to this terminal while it is attached to the container or exec
+/// instance at `path`: with `tty` a resized window resizes its TTY, and with `proxy`
+/// interrupts and terminations are sent to the container, as the docker CLI does.
+fn forward_signals(path: &str, tty: bool, proxy: bool) {
+    let resize = |path: &str| {
+        if let Some((rows, columns)) = terminal_size() {
+            let _ = engine_request("POST", &format!("{}/resize?h={}&w={}", path, rows, columns), None);
+        }
+    };
+
+    let mut signals = Vec::new();
+    if tty {
+        resize(path);
+        signals.push(libc::SIGWINCH);
+    }
+    if proxy {
+        signals.extend([libc::SIGINT, libc::SIGTERM, libc::SIGHUP, libc::SIGQUIT]);
+    }
+    for signal in signals {
+        // SAFETY: the handler only stores the signal number
+        unsafe { libc::signal(signal, record_signal as *const () as libc::sighandler_t) };
+    }
+
+    let path = path.to_string();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_millis(50));
+        match RECEIVED_SIGNAL.swap(0, Ordering::SeqCst) {
+            0 => {}
+            libc::SIGWINCH => resize(&path),
+            signal => {
+                let _ = engine_request("POST", &format!("{}/kill?signal={}", path, signal), None);
+            }
+        }
+    });
+}
+
+/// Copies this terminal's input to the taken over connection of a container or exec instance,
+/// with `stdin`, and what it writes back to stdout and stderr until it closes the connection.
+fn pump_attached_streams(stream: UnixStream, tty: bool, stdin: bool) {
+    if stdin && let Ok(mut input) = stream.try_clone() {
+        std::thread::spawn(move || {
+            let _ = std::io::copy(&mut std::io::stdin(), &mut input);
+            // Lets the container see the end of its input
+            let _ = input.shutdown(std::net::Shutdown::Write);
+        });
+    }
+
+    let _ = copy_container_output(stream, tty);
+}
+
+/// Writes what a container outputs to stdout and stderr. Without a TTY docker multiplexes the
+/// two, prefixing each frame with the stream it belongs to and its length.
+fn copy_container_output(mut output: impl Read, tty: bool) -> std::io::Result<()> {
+    if tty {
+        let mut buffer = [0; 8192];
+        loop {
+            let read = output.read(&mut buffer)?;
+            if read == 0 {
+                return Ok(());
+            }
+            // Flushed right away, a prompt does not end in a newline
+            let mut stdout = std::io::stdout();
+            stdout.write_all(&buffer[..read])?;
+            stdout.flush()?;
+        }
+    }
+
+    let mut header = [0; 8];
+    loop {
+        match output.read_exact(&mut header) {
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+
+        let length = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let mut frame = (&mut output).take(length as u64);
+        if header[0] == 2 {
+            std::io::copy(&mut frame, &mut std::io::stderr())?;
+        } else {
+            let mut stdout = std::io::stdout();
+            std::io::copy(&mut frame, &mut stdout)?;
+            stdout.flush()?;
+        }
+    }
+}
+
+/// Creates and starts a container through the Engine API like `docker run` with these
+/// arguments, attached to this terminal unless detached. Returns the exit code of its command,
+/// 0 when detached. None if the API cannot be reached or `args` need the CLI.
+fn engine_run(args: &[&str]) -> Option<Result<i32, Error>> {
+    let run = RunArgs::parse(args.get(1..)?)?;
+
+    let mut query = Vec::new();
+    if let Some(name) = &run.name {
+        query.push(format!("name={}", encode_path_segment(name)));
+    }
+    if let Some(platform) = &run.platform {
+        query.push(format!("platform={}", encode_path_segment(platform)));
+    }
+
+    let (status, body) = engine_request("POST", &format!("/containers/create?{}", query.join("&")), Some(&run.create_body()))?;
+    if status != 201 {
+        return Some(Err(Error::DockerError(format!("Failed to create container: {}", engine_error(&body)))));
+    }
+
+    Some(created_id(&body).and_then(|id| {
+        let path = format!("/containers/{}", id);
+
+        if run.detach {
+            return engine_start(&path).map(|()| 0);
+        }
+
+        let stream = engine_hijack(&format!("{}/attach?stream=1&stdin={}&stdout=1&stderr=1", path, run.interactive), None)?;
+
+        // Waits from before the start, so a quick exit, or the removal of a --rm container, is not missed
+        let condition = if run.auto_remove { "removed" } else { "next-exit" };
+        let wait = engine_send("POST", &format!("{}/wait?condition={}", path, condition), None, false)
+            .filter(|wait| wait.status == 200)
+            .ok_or_else(engine_unreachable)?;
+        let _ = wait.stream.set_read_timeout(None);
+
+        engine_start(&path)?;
+
+        let terminal = RawTerminal::enable(run.interactive);
+        forward_signals(&path, run.interactive, true);
+        pump_attached_streams(stream, run.interactive, run.interactive);
+        drop(terminal);
+
+        let exited = wait.body()
+            .and_then(|body| serde_json::from_slice::<serde_json::Value>(&body).ok())
+            .ok_or_else(engine_unreachable)?;
+        Ok(exited.get("StatusCode").and_then(|code| code.as_i64()).unwrap_or(0) as i32)
+    }))
+}
+
+/// Runs a command in a running container through the Engine API like `docker exec` with these
+/// arguments, attached to this terminal, and returns its exit code. None if the API cannot be
+/// reached or `args` need the CLI.
+fn engine_exec(args: &[&str]) -> Option<Result<i32, Error>> {
+    let exec = RunArgs::parse(args.get(1..)?)?;
+
+    let (status, body) = engine_request("POST", &format!("/containers/{}/exec", encode_path_segment(&exec.target)), Some(&exec.exec_body()))?;
+    if status != 201 {
+        return Some(Err(Error::DockerError(format!("Failed to execute in container '{}': {}", exec.target, engine_error(&body)))));
+    }
+
+    Some(created_id(&body).and_then(|id| {
+        let path = format!("/exec/{}", id);
+        let stream = engine_hijack(&format!("{}/start", path), Some(&serde_json::json!({ "Detach": false, "Tty": exec.interactive })))?;
+
+        let terminal = RawTerminal::enable(exec.interactive);
+        forward_signals(&path, exec.interactive, false);
+        pump_attached_streams(stream, exec.interactive, exec.interactive);
+        drop(terminal);
+
+        // The exit code is recorded just after the streams close
+        for _ in 0..50 {
+            let (_, body) = engine_request("GET", &format!("{}/json", path), None).ok_or_else(engine_unreachable)?;
+            let exec = serde_json::from_slice::<serde_json::Value>(&body).unwrap_or_default();
+            if exec.get("Running").and_then(|running| running.as_bool()) != Some(true)
+                && let Some(code) = exec.get("ExitCode").and_then(|code| code.as_i64()) {
+                return Ok(code as i32);
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        Err(Error::DockerError("The command did not report an exit code".to_string()))
+    }))
+}
+
+/// Attaches this terminal to a running container through the Engine API like `docker attach`.
+/// Returns the exit code of its main process once it stops, or 0 if detached from with
+/// ctrl-p ctrl-q. None if the API cannot be reached.
+fn engine_attach(name: &str) -> Option<Result<i32, Error>> {
+    let info = match engine_inspect::<ContainerInspect>("container", &[name])? {
+        Ok(found) => found.into_iter().next()?,
+        Err(e) => return Some(Err(e)),
+    };
+    let tty = info.config.tty;
+    let path = format!("/containers/{}", info.id);
+
+    Some(engine_hijack(&format!("{}/attach?stream=1&stdin=1&stdout=1&stderr=1", path), None).and_then(|stream| {
+        let terminal = RawTerminal::enable(tty);
+        forward_signals(&path, tty, true);
+        pump_attached_streams(stream, tty, true);
+        drop(terminal);
+
+        Ok(match inspect_container(&info.id)? {
+            Some(info) if info.state.status != ContainerState::Running => info.state.exit_code as i32,
+            _ => 0,
+        })
+    }))
+}
+
+/// Writes the output of a container like `docker logs`, following it with `follow`. None if
+/// the API cannot be reached, or `since` is not a Unix timestamp or a duration such as `42m`,
+/// which are left to the CLI.
+fn engine_logs(name: &str, follow: bool, since: Option<&str>) -> Option<Result<(), Error>> {
+    let since = match since {
+        None => 0,
+        Some(since) if since.chars().all(|c| c.is_ascii_digit()) => since.parse().ok()?,
+        Some(since) if since.ends_with(['s', 'm', 'h', 'd']) => unix_now().saturating_sub(parse_duration(since)?),
+        Some(_) => return None,
+    };
+
+    let info = match engine_inspect::<ContainerInspect>("container", &[name])? {
+        Ok(found) => found.into_iter().next()?,
+        Err(e) => return Some(Err(e)),
+    };
+
+    let path = format!("/containers/{}/logs?stdout=1&stderr=1&follow={}&since={}", info.id, follow, since);
+    let response = engine_send("GET", &path, None, false)?;
+    if response.status != 200 {
+        let reason = engine_error(&response.body().unwrap_or_default());
+        return Some(Err(Error::DockerError(format!("Failed to read the logs of '{}': {}", name, reason))));
+    }
+
+    let _ = response.stream.set_read_timeout(None);
+    let _ = copy_container_output(response.reader(), info.config.tty);
+    Some(Ok(()))
+}
+
+/// Does what `docker run`, `exec`, `attach` or `logs` with these arguments would, attached to
+/// this terminal, through the Engine API. Returns the exit code of the command. None if the API
+/// cannot be reached or the arguments need the CLI.
+fn engine_attached(args: &[&str]) -> Option<Result<i32, Error>> {
+    match *args {
+        ["run", ..] => engine_run(args),
+        ["exec", ..] => engine_exec(args),
+        ["attach", name] => engine_attach(name),
+        ["logs", ref flags @ .., name] => {
+            let (mut follow, mut since) = (false, None);
+            let mut flags = flags.iter();
+            while let Some(&flag) = flags.next() {
+                match flag {
+                    "-f" => follow = true,
+                    "--since" => since = Some(*flags.next()?),
+                    _ => return None,
+                }
+            }
+            Some(engine_logs(name, follow, since)?.map(|()| 0))
+        }
+        _ => None,
+    }
+}
+
+/// Inspects the given containers or images (`kind`), through the Engine API when it can be
+/// reached and `docker <kind> inspect` otherwise. Objects that do not exist are left out rather
+/// than reported as an error.
+fn docker_inspect<T: DeserializeOwned>(kind: &str, references: &[&str]) -> Result<Vec<T>, Error> {
+    if let Some(found) = engine_inspect(kind, references) {
+        return found;
+    }
+
+    let result = Command::new("docker")
+        .args([kind, "inspect"])
+        .args(references)
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| Error::CommandError {
+            cmd: format!("docker {} inspect {}", kind, references.join(" ")),
+            reason: e.to_string()
+        })?;
+
+    // Exits non-zero if any reference is missing, but still prints the ones that exist
+    let output = String::from_utf8_lossy(&result.stdout);
+    if output.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    serde_json::from_str(&output)
+        .map_err(|e| Error::DockerError(format!("Unexpected output from docker {} inspect: {}", kind, e)))
+}
+
+fn inspect_container(name: &str) -> Result<Option<ContainerInspect>, Error> {
+    Ok(docker_inspect("container", &[name])?.into_iter().next())
+}
+
+fn inspect_image(image: &str) -> Result<Option<ImageInspect>, Error> {
+    Ok(docker_inspect("image", &[image])?.into_iter().next())
+}
+
+/// Looks up the contain-managed container with exactly this name. docker also resolves ID
+/// prefixes, so the name is compared once more, and containers contain did not create are
+/// left out.
+fn get_container_info(name: &str) -> Result<Option<ContainerInspect>, Error> {
+    Ok(inspect_container(name)?
+        .filter(|info| info.name.trim_start_matches('/') == name)
+        .filter(|info| info.config.label(MANAGED_LABEL) == Some("true")))
+}
+
+fn container_state(name: &str) -> Result<Option<ContainerState>, Error> {
+    Ok(get_container_info(name)?.map(|info| info.state.status))
+}
+
+/// Labels put on every container contain creates, so they can be told apart from unrelated
+/// containers and traced back to the project, configuration file and entry they belong to.
+fn container_labels(c: &Configuration) -> Vec<String> {
+    vec![
+        format!("{}=true", MANAGED_LABEL),
+        format!("{}={}", VERSION_LABEL, env!("CARGO_PKG_VERSION")),
+        format!("{}={}", ROOT_PATH_LABEL, c.root_path.display()),
+        format!("{}={}", CONFIG_FILE_LABEL, c.config_file.display()),
+        format!("{}={}", ENTRY_LABEL, c.entry),
+        format!("{}={}", CREATED_AT_LABEL, unix_now()),
+    ]
+}
+
+/// Names of the docker volumes mounted by the configuration.
+fn named_volumes(c: &Configuration) -> Vec<String> {
+    c.extra_mounts.iter()
+        .filter(|mount| mount.split(',').any(|option| option == "type=volume"))
+        .filter_map(|mount| mount.split(',').find_map(|option| option.strip_prefix("src=")))
+        .filter(|src| !src.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Creates the named volumes of the configuration up front, labelled with the project they
+/// belong to, so 'contain gc' can find them once the project is gone. Volumes that already
+/// exist are left as they are.
+fn ensure_volumes(c: &Configuration, options: &GlobalOptions) -> Result<(), Error> {
+    let label = format!("{}={}", ROOT_PATH_LABEL, c.root_path.display());
+
+    for volume in named_volumes(c) {
+        let docker_args = vec!["volume", "create", "--label", label.as_str(), volume.as_str()];
+
+        if options.dry_run {
+            println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&docker_args));
+            continue;
+        }
+
+        let succeeded = docker_command(&docker_args, false)
+            .map_err(|e| Error::CommandError {
+                cmd: format!("docker volume create {}", volume),
+                reason: e.to_string()
+            })?;
+
+        if !succeeded {
+            return Err(Error::DockerError(format!("Failed to create volume '{}'", volume)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Hashes the parts of the resolved configuration that are baked into a background
+/// container at creation time, with the environment and ports given on the command line
+/// applied. Uses FNV-1a so the value is stable across builds.
+fn config_hash(c: &Configuration, options: &GlobalOptions) -> String {
+    let env_variables = [&c.env_variables[..], &options.cli_env_variables[..]].concat();
+    let ports: &[String] = if options.skip_ports { &[] } else { &c.ports };
+
+    fnv1a(&format!("{:?}", (
+        &c.run_image(), &c.root_path, &c.workdir_path, &c.flags, &env_variables,
+        &c.extra_mounts, ports, &c.up_command, &c.restart, &c.idle_timeout, &c.platform,
+    )))
+}
+
+fn fnv1a(repr: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in repr.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    format!("{:016x}", hash)
+}
+
+/// Hashes the inputs of an image build: the Dockerfile contents and the build options.
+/// Files copied from the build context are not included.
+fn build_hash(c: &Configuration) -> String {
+    let dockerfile = match (&c.dockerfile_inline, &c.dockerfile) {
+        (Some(inline), _) => inline.clone(),
+        (None, Some(dockerfile)) => fs::read_to_string(c.root_path.join(dockerfile)).unwrap_or_default(),
+        (None, None) => String::new(),
+    };
+
+    fnv1a(&format!("{:?}", (
+        dockerfile, &c.build_args, &c.build_context, &c.build_target, &c.platform, c.buildx,
+    )))
+}
+
+/// Checks whether the container was created from a different configuration than the current one.
+/// Containers without a config hash label (created by older versions) are not considered drifted.
+fn container_has_drifted(c: &Configuration, info: &ContainerInspect, options: &GlobalOptions) -> bool {
+    match info.config.label(CONFIG_HASH_LABEL) {
+        Some(hash) => hash != config_hash(c, options),
+        None => false,
+    }
+}
+
+fn warn_config_drift(name: &str) {
+    println!("{} Container '{}' was created from an older .contain.yaml", "(warning)   ".yellow().bold(), name);
+    println!("{} Use 'contain up --recreate' to apply the current configuration", "(hint)      ".blue().bold());
+}
+
+/// Force-removes a container regardless of its state, used when recreating it.
+fn remove_container(name: &str, options: &GlobalOptions) -> Result<bool, Error> {
+    let docker_args = vec!["rm", "-f", name];
+
+    if options.dry_run {
+        println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&docker_args));
+        return Ok(true);
+    }
+
+    println!("{} Removing container '{}'...", "(removing)  ".yellow().bold(), name);
+    println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(&docker_args));
+
+    let succeeded = docker_command(&docker_args, false)
+        .map_err(|e| Error::ContainerRemoveFailed {
+            name: name.to_string(),
+            reason: e.to_string()
+        })?;
+
+    if !succeeded {
+        return Err(Error::ContainerRemoveFailed {
+            name: name.to_string(),
+            reason: "docker rm returned non-zero exit code".to_string()
+        });
+    }
+
+    Ok(true)
+}
+
+fn container_up(options: GlobalOptions, recreate: bool) -> Result<bool, Error> {
+    let (mut config, name) = require_named_config("up", &options)?;
+
+    println!("{} {}", "(configuration)".blue().bold(), config.config_file.display());
+    if let Some(profile) = &config.profile {
+        println!("{} {}", "(profile)      ".blue().bold(), profile);
+    }
+
+    // Check for passthrough mode
+    if is_inside_container() {
+        return Err(Error::UnsupportedParameters("'contain up' cannot run inside a container".to_string()));
+    }
+
+    start_background_container(&mut config, &name, &options, recreate, false)?;
+
+    println!("{} Use 'contain run <command>' or 'contain shell' to execute commands", "(hint)      ".blue().bold());
+    if config.up_command.is_some() {
+        println!("{} Use 'contain logs -f' to follow the container output", "(hint)      ".blue().bold());
+    }
+    println!("{} Use 'contain down' to stop and remove the container", "(hint)      ".blue().bold());
+
+    Ok(true)
+}
+
+/// Brings the named background container up: starts a stopped container that still matches
+/// the configuration, unpauses a paused one, recreates a dead, drifted or `recreate`d one, or
+/// creates it from scratch. `image_ready` says the caller already made sure the image exists.
+fn start_background_container(config: &mut Configuration, name: &str, options: &GlobalOptions, recreate: bool, image_ready: bool) -> Result<bool, Error> {
+    let info = if options.dry_run { None } else { get_container_info(name)? };
+    let state = info.as_ref().map(|info| info.state.status);
+
+    if recreate {
+        // Replace whatever exists, whatever its state
+        if state.is_some() || options.dry_run {
+            remove_container(name, options)?;
+        }
+    } else {
+        match state {
+            Some(ContainerState::Running) => {
+                if info.as_ref().is_some_and(|info| container_has_drifted(config, info, options)) {
+                    warn_config_drift(name);
+                }
+                return Err(Error::ContainerAlreadyRunning { name: name.to_string() });
+            }
+            Some(ContainerState::Restarting) => {
+                return Err(Error::ContainerRestarting { name: name.to_string() });
+            }
+            Some(ContainerState::Paused) => {
+                return unpause_container(name, options);
+            }
+            Some(ContainerState::Unknown) => {
+                return Err(Error::ContainerStateUnknown { name: name.to_string() });
+            }
+            Some(stopped) if stopped.is_stopped() => {
+                // A stopped container is only reused if it still matches the configuration
+                if info.as_ref().is_some_and(|info| container_has_drifted(config, info, options)) {
+                    println!("{} Stopped container '{}' was created from an older .contain.yaml, recreating it", "(recreating)".yellow().bold(), name);
+                    remove_container(name, options)?;
+                } else {
+                    println!("{} Starting stopped container '{}'", "(starting)".green().bold(), name);
+                    return start_stopped_container(name, options);
+                }
+            }
+            // Dead, it cannot be started again
+            Some(_) => {
+                println!("{} Container '{}' is dead, recreating it", "(recreating)".yellow().bold(), name);
+                remove_container(name, options)?;
+            }
+            None => {}
+        }
+    }
+
+    if !image_ready {
+        ensure_image(config, options)?;
+    }
+    ensure_volumes(config, options)?;
+
+    // Start container in detached mode
+    docker_run_detached(config, name, options)
+}
+
+fn docker_run_detached(c: &Configuration, name: &str, options: &GlobalOptions) -> Result<bool, Error> {
+    let uid = get_current_uid();
+    let gid = get_current_gid();
+    let uid_gid = format!("{}:{}", uid, gid);
+
+    let mount = format!("type=bind,src={},dst={}", c.root_path.to_str().unwrap(), c.workdir_path);
+
+    let mut docker_args: Vec<String> = vec!["run".to_string()];
+
+    // Always detached for 'up'
+    docker_args.push("-d".to_string());
+
+    // Always use the container name
+    docker_args.push("--name".to_string());
+    docker_args.push(name.to_string());
+
+    // Record the configuration so drift can be detected later
+    for label in container_labels(c) {
+        docker_args.push("--label".to_string());
+        docker_args.push(label);
+    }
+    docker_args.push("--label".to_string());
+    docker_args.push(format!("{}={}", CONFIG_HASH_LABEL, config_hash(c, options)));
+
+    // Let the idle reaper know when this container may be stopped
+    if let Some(timeout) = c.idle_timeout {
+        docker_args.push("--label".to_string());
+        docker_args.push(format!("{}={}", IDLE_TIMEOUT_LABEL, timeout));
+    }
+
+    // User mapping (unless root flag)
+    if !options.run_as_root && !c.flags.contains(&"root".to_string()) {
+        docker_args.push("-u".to_string());
+        docker_args.push(uid_gid);
+    }
+
+    // Working directory
+    docker_args.push("-w".to_string());
+    docker_args.push(c.workdir_path.clone());
+
+    // Environment variables
+    let all_env_variables = [&c.env_variables[..], &options.cli_env_variables[..]].concat();
+    for item in &all_env_variables {
+        docker_args.push("-e".to_string());
+        docker_args.push(item.trim().to_string());
+    }
+
+    // Mount workspace
+    docker_args.push("--mount".to_string());
+    docker_args.push(mount);
+
+    // Extra mounts
+    for item in &c.extra_mounts {
+        docker_args.push("--mount".to_string());
+        docker_args.push(item.clone());
+    }
+
+    // Ports (unless skip_ports)
+    if !options.skip_ports {
+        for item in &c.ports {
+            docker_args.push("-p".to_string());
+            docker_args.push(item.clone());
+        }
+    }
+
+    // Privileged flag
+    if c.flags.contains(&"privileged".to_string()) {
+        docker_args.push("--privileged".to_string());
+    }
+
+    // Run cross-arch images under emulation
+    if let Some(platform) = &c.platform {
+        docker_args.push("--platform".to_string());
+        docker_args.push(platform.clone());
+    }
+
+    // Restart policy
+    if let Some(restart) = &c.restart {
+        docker_args.push("--restart".to_string());
+        docker_args.push(restart.clone());
+    }
+
+    // Image
+    docker_args.push(c.run_image());
+
+    // Service command, or an idle command to keep container running
+    match &c.up_command {
+        Some(argv) => docker_args.extend(argv.iter().cloned()),
+        None => {
+            docker_args.push("sleep".to_string());
+            docker_args.push("infinity".to_string());
+        }
+    }
+
+    let args_refs: Vec<&str> = docker_args.iter().map(|s| s.as_str()).collect();
+
+    if options.dry_run {
+        println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&args_refs));
+        return Ok(true);
+    }
+
+    println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(&args_refs));
+
+    let succeeded = match engine_run(&args_refs) {
+        Some(started) => started.map_err(|e| Error::DockerError(format!("Failed to start container '{}': {}", name, e)))? == 0,
+        None => Command::new("docker")
+            .args(&args_refs)
+            .status()
+            .map_err(|e| Error::CommandError {
+                cmd: format!("docker {}", args_refs.join(" ")),
+                reason: e.to_string()
+            })?
+            .success(),
+    };
+
+    if succeeded {
+        record_container_use(name);
+        println!("{} Container '{}' is now running in the background", "(success)".green().bold(), name);
+        Ok(true)
+    } else {
+        Err(Error::DockerError(format!("Failed to start container '{}'", name)))
+    }
+}
+
+fn unpause_container(name: &str, options: &GlobalOptions) -> Result<bool, Error> {
+    let docker_args = vec!["unpause", name];
+
+    if options.dry_run {
+        println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&docker_args));
+        return Ok(true);
+    }
+
+    println!("{} Unpausing container '{}'", "(starting)".green().bold(), name);
+    println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(&docker_args));
+
+    let succeeded = docker_command(&docker_args, false)
+        .map_err(|e| Error::CommandError {
+            cmd: format!("docker unpause {}", name),
+            reason: e.to_string()
+        })?;
+
+    if succeeded {
+        record_container_use(name);
+        println!("{} Container '{}' is now running", "(success)".green().bold(), name);
+        Ok(true)
+    } else {
+        Err(Error::DockerError(format!("Failed to unpause container '{}'", name)))
+    }
+}
+
+fn start_stopped_container(name: &str, options: &GlobalOptions) -> Result<bool, Error> {
+    let docker_args = vec!["start", name];
+
+    if options.dry_run {
+        println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&docker_args));
+        return Ok(true);
+    }
+
+    println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(&docker_args));
+
+    let succeeded = docker_command(&docker_args, false)
+        .map_err(|e| Error::CommandError {
+            cmd: format!("docker start {}", name),
+            reason: e.to_string()
+        })?;
+
+    if succeeded {
+        record_container_use(name);
+        println!("{} Container '{}' is now running", "(success)".green().bold(), name);
+        Ok(true)
+    } else {
+        Err(Error::DockerError(format!("Failed to start container '{}'", name)))
+    }
+}
+
+fn container_down(options: GlobalOptions) -> Result<bool, Error> {
+    let (config, name) = require_named_config("down", &options)?;
+
+    println!("{} {}", "(configuration)".blue().bold(), config.config_file.display());
+
+    // Check for passthrough mode
+    if is_inside_container() {
+        return Err(Error::UnsupportedParameters("'contain down' cannot run inside a container".to_string()));
+    }
+
+    let state = if options.dry_run { None } else { container_state(&name)? };
+
+    if !options.dry_run && state.is_none() {
+        println!("{} Container '{}' does not exist", "(info)      ".blue().bold(), &name);
+        return Ok(true);
+    }
+
+    // Paused, restarting, dead and unknown containers cannot be stopped gracefully, so they are removed by force
+    if matches!(state, Some(ContainerState::Paused | ContainerState::Restarting | ContainerState::Dead | ContainerState::Unknown)) {
+        remove_container(&name, &options)?;
+        println!("{} Container '{}' removed", "(removed)   ".green().bold(), &name);
+        return Ok(true);
+    }
+
+    // Stop the container if running
+    if state == Some(ContainerState::Running) || options.dry_run {
+        let stop_args = vec!["stop", name.as_str()];
+
+        if options.dry_run {
+            println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&stop_args));
+        } else {
+            println!("{} Stopping container '{}'...", "(stopping)  ".yellow().bold(), &name);
+            println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(&stop_args));
+
+            let succeeded = docker_command(&stop_args, false)
+                .map_err(|e| Error::ContainerStopFailed {
+                    name: name.clone(),
+                    reason: e.to_string()
+                })?;
+
+            if !succeeded {
+                return Err(Error::ContainerStopFailed {
+                    name: name.clone(),
+                    reason: "docker stop returned non-zero exit code".to_string()
+                });
+            }
+
+            println!("{} Container '{}' stopped", "(stopped)   ".green().bold(), &name);
+        }
+    }
+
+    // Remove the container
+    let rm_args = vec!["rm", name.as_str()];
+
+    if options.dry_run {
+        println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&rm_args));
+    } else {
+        println!("{} Removing container '{}'...", "(removing)  ".yellow().bold(), &name);
+        println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(&rm_args));
+
+        let succeeded = docker_command(&rm_args, false)
+            .map_err(|e| Error::ContainerRemoveFailed {
+                name: name.clone(),
+                reason: e.to_string()
+            })?;
+
+        if !succeeded {
+            return Err(Error::ContainerRemoveFailed {
+                name: name.clone(),
+                reason: "docker rm returned non-zero exit code".to_string()
+            });
+        }
+
+        println!("{} Container '{}' removed", "(removed)   ".green().bold(), &name);
+    }
+
+    Ok(true)
+}
+
+fn container_status(options: GlobalOptions) -> Result<bool, Error> {
+    let (config, name) = require_named_config("status", &options)?;
+
+    println!("{} {}", "(configuration)".blue().bold(), config.config_file.display());
+    println!();
+
+    // Check for passthrough mode
+    if is_inside_container() {
+        println!("{} Running inside container (passthrough mode)", "(status)    ".blue().bold());
+        return Ok(true);
+    }
+
+    if options.dry_run {
+        println!("{} docker ps -a --filter label={}=true --format ... ({})", "(dry run)      ".yellow().bold(), MANAGED_LABEL, &name);
+        return Ok(true);
+    }
+
+    // Get container info
+    match get_container_info(&name)? {
+        Some(info) => {
+            // Containers without a config hash label (created by older versions) are not considered drifted
+            let drifted = info.config.label(CONFIG_HASH_LABEL).is_some_and(|hash| hash != config_hash(&config, &options));
+            let state = info.state.status;
+            let status = match state {
+                ContainerState::Running => format!("running since {}", format_timestamp(&info.state.started_at)),
+                ContainerState::Exited => format!("exited with code {} at {}", info.state.exit_code, format_timestamp(&info.state.finished_at)),
+                ContainerState::Restarting => format!("restarting (last exit code {})", info.state.exit_code),
+                other => other.as_str().to_string(),
+            };
+            let ports = info.published_ports();
+
+            println!("{}", "Container Status".bold());
+            println!("{}", "=".repeat(50));
+            println!("{:<15} {}", "Name:".bold(), info.name.trim_start_matches('/'));
+            println!("{:<15} {}", "ID:".bold(), short_id(&info.id));
+            println!("{:<15} {}", "Image:".bold(), info.config.image);
+            println!("{:<15} {}",
+                "Status:".bold(),
+                match state {
+                    ContainerState::Running => status.green().to_string(),
+                    ContainerState::Paused | ContainerState::Restarting => status.yellow().to_string(),
+                    _ => status.red().to_string(),
+                });
+            if let Some(health) = &info.state.health {
+                println!("{:<15} {}",
+                    "Health:".bold(),
+                    match health.status.as_str() {
+                        "healthy" => health.status.green().to_string(),
+                        "unhealthy" => format!("unhealthy ({} failing checks)", health.failing_streak).red().to_string(),
+                        _ => health.status.yellow().to_string(),
+                    });
+            }
+            println!("{:<15} {}", "Created:".bold(), format_timestamp(&info.created));
+            if !ports.is_empty() {
+                println!("{:<15} {}", "Ports:".bold(), ports.join(", "));
+            }
+            println!("{:<15} {}",
+                "Config:".bold(),
+                if drifted {
+                    "Out of date".yellow().to_string()
+                } else {
+                    "Up to date".green().to_string()
+                });
+            println!();
+
+            if drifted {
+                println!("{} Use 'contain up --recreate' to apply the current .contain.yaml", "(hint)      ".blue().bold());
+            }
+
+            match state {
+                ContainerState::Running => {
+                    println!("{} Use 'contain run <command>' or 'contain shell' to execute commands", "(hint)      ".blue().bold());
+                    println!("{} Use 'contain stop' to stop the container, or 'contain down' to also remove it", "(hint)      ".blue().bold());
+                }
+                ContainerState::Paused => {
+                    println!("{} Use 'contain up' to unpause the container", "(hint)      ".blue().bold());
+                }
+                ContainerState::Restarting => {
+                    println!("{} Use 'contain logs' to see why the main process keeps exiting", "(hint)      ".blue().bold());
+                }
+                ContainerState::Dead => {
+                    println!("{} Use 'contain up' to recreate the container", "(hint)      ".blue().bold());
+                }
+                ContainerState::Unknown => {
+                    println!("{} Use 'contain down' to remove the container", "(hint)      ".blue().bold());
+                }
+                ContainerState::Created | ContainerState::Exited => {
+                    println!("{} Use 'contain up' to start the container", "(hint)      ".blue().bold());
+                    println!("{} Use 'contain down' to remove the stopped container", "(hint)      ".blue().bold());
+                }
+            }
+        }
+        None => {
+            println!("{}", "Container Status".bold());
+            println!("{}", "=".repeat(50));
+            println!("{:<15} {}", "Name:".bold(), &name);
+            println!("{:<15} {}", "Status:".bold(), "Not created".yellow());
+            println!();
+            println!("{} Use 'contain up' to create and start the container", "(hint)      ".blue().bold());
+        }
+    }
+
+    Ok(true)
+}
+
+/// Resolves the named container for a lifecycle command (logs, stop, restart, attach)
+/// and refuses to run in passthrough mode.
+fn require_background_container(command_name: &str, options: &GlobalOptions) -> Result<String, Error> {
+    let (config, name) = require_named_config(command_name, options)?;
+
+    println!("{} {}", "(configuration)".blue().bold(), config.config_file.display());
+
+    // Check for passthrough mode
+    if is_inside_container() {
+        return Err(Error::UnsupportedParameters(format!("'contain {}' cannot run inside a container", command_name)));
+    }
+
+    Ok(name)
+}
+
+fn container_logs(options: GlobalOptions, follow: bool, since: Option<&str>) -> Result<bool, Error> {
+    let name = require_background_container("logs", &options)?;
+
+    let mut docker_args: Vec<&str> = vec!["logs"];
+
+    if follow {
+        docker_args.push("-f");
+    }
+
+    if let Some(since) = since {
+        docker_args.push("--since");
+        docker_args.push(since);
+    }
+
+    docker_args.push(&name);
+
+    execute_command(options, "docker", docker_args);
+
+    Ok(true)
+}
+
+fn container_stop(options: GlobalOptions) -> Result<bool, Error> {
+    let name = require_background_container("stop", &options)?;
+
+    if !options.dry_run {
+        match container_state(&name)? {
+            Some(ContainerState::Running | ContainerState::Paused | ContainerState::Restarting) => {}
+            Some(state) => {
+                println!("{} Container '{}' is not running ({})", "(info)      ".blue().bold(), &name, state.as_str());
+                return Ok(true);
+            }
+            None => {
+                println!("{} Container '{}' does not exist", "(info)      ".blue().bold(), &name);
+                return Ok(true);
+            }
+        }
+    }
+
+    let docker_args = vec!["stop", name.as_str()];
+
+    if options.dry_run {
+        println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&docker_args));
+        return Ok(true);
+    }
+
+    println!("{} Stopping container '{}'...", "(stopping)  ".yellow().bold(), &name);
+    println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(&docker_args));
+
+    let succeeded = docker_command(&docker_args, false)
+        .map_err(|e| Error::ContainerStopFailed {
+            name: name.clone(),
+            reason: e.to_string()
+        })?;
+
+    if !succeeded {
+        return Err(Error::ContainerStopFailed {
+            name: name.clone(),
+            reason: "docker stop returned non-zero exit code".to_string()
+        });
+    }
+
+    println!("{} Container '{}' stopped", "(stopped)   ".green().bold(), &name);
+    println!("{} Use 'contain up' to start it again", "(hint)      ".blue().bold());
+
+    Ok(true)
+}
+
+fn container_restart(options: GlobalOptions) -> Result<bool, Error> {
+    let name = require_background_container("restart", &options)?;
+
+    if !options.dry_run && container_state(&name)?.is_none() {
+        return Err(Error::ContainerNotFound { name });
+    }
+
+    let docker_args = vec!["restart", name.as_str()];
+
+    if options.dry_run {
+        println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&docker_args));
+        return Ok(true);
+    }
+
+    println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(&docker_args));
+
+    let succeeded = docker_command(&docker_args, false)
+        .map_err(|e| Error::CommandError {
+            cmd: format!("docker restart {}", name),
+            reason: e.to_string()
+        })?;
+
+    if succeeded {
+        println!("{} Container '{}' restarted", "(success)".green().bold(), &name);
+        Ok(true)
+    } else {
+        Err(Error::DockerError(format!("Failed to restart container '{}'", name)))
+    }
+}
+
+fn container_attach(options: GlobalOptions) -> Result<bool, Error> {
+    let name = require_background_container("attach", &options)?;
+
+    if !options.dry_run && !container_exists(&name)? {
+        return Err(Error::ContainerNotRunning { name });
+    }
+
+    execute_command(options, "docker", vec!["attach", name.as_str()]);
+
+    Ok(true)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Directory for contain's own bookkeeping ($XDG_STATE_HOME/contain or ~/.local/state/contain).
+fn state_dir() -> Option<PathBuf> {
+    let base = match env::var("XDG_STATE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var("HOME").ok()?).join(".local/state"),
+    };
+
+    Some(base.join("contain"))
+}
+
+fn last_used_path(name: &str) -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("last-used").join(name))
+}
+
+/// Records that the named container was just used. Failures are ignored since this
+/// bookkeeping must never get in the way of running the actual command.
+fn record_container_use(name: &str) {
+    if let Some(path) = last_used_path(name) {
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let _ = fs::write(path, unix_now().to_string());
+    }
+}
+
+fn container_last_used(name: &str) -> Option<u64> {
+    let contents = fs::read_to_string(last_used_path(name)?).ok()?;
+    contents.trim().parse().ok()
+}
+
+/// Runs the idle reaper unless it already ran within the last REAP_INTERVAL_SECS.
+/// The background container of the project in the current directory is left alone.
+fn reap_idle_containers_throttled() {
+    let stamp = match state_dir() {
+        Some(dir) => dir.join("last-reap"),
+        None => return,
+    };
+
+    let last_reap = fs::read_to_string(&stamp).ok()
+        .and_then(|contents| contents.trim().parse::<u64>().ok())
+        .unwrap_or(0);
+
+    if unix_now().saturating_sub(last_reap) < REAP_INTERVAL_SECS {
+        return;
+    }
+
+    if let Some(parent) = stamp.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let _ = fs::write(&stamp, unix_now().to_string());
+
+    let options = GlobalOptions {
+        interactive: false,
+        keep_container: false,
+        run_as_root: false,
+        dry_run: false,
+        skip_ports: false,
+        skip_name: false,
+        cli_env_variables: Vec::new(),
+        overrides: ConfigOverrides::default(),
+    };
+
+    let current_path = env::current_dir().ok();
+    let _ = reap_idle_containers(&options, current_path.as_deref());
+}
+
+/// Stops running containers labelled with an idle timeout whose last recorded use is older
+/// than that timeout. Containers belonging to a project that contains `active_path` are skipped.
+fn reap_idle_containers(options: &GlobalOptions, active_path: Option<&Path>) -> Result<Vec<String>, Error> {
+    let containers = managed_containers(false, &[format!("label={}", IDLE_TIMEOUT_LABEL)],
+        &[IDLE_TIMEOUT_LABEL, ROOT_PATH_LABEL])?;
+    let now = unix_now();
+    let mut stopped = Vec::new();
+
+    for container in &containers {
+        let (name, root_path) = (container.name(), container.label(ROOT_PATH_LABEL));
+        let timeout = match container.label(IDLE_TIMEOUT_LABEL).parse::<u64>() {
+            Ok(t) => t,
+            Err(_) => continue,
+        };
+
+        if let Some(active) = active_path
+            && !root_path.is_empty()
+            && active.starts_with(root_path) {
+            continue;
+        }
+
+        let last_used = match container_last_used(name) {
+            Some(t) => t,
+            None => {
+                // No record yet, start the clock now rather than stopping it blindly
+                record_container_use(name);
+                continue;
+            }
+        };
+
+        let idle = now.saturating_sub(last_used);
+        if idle < timeout {
+            continue;
+        }
+
+        let docker_args = vec!["stop", name];
+
+        if options.dry_run {
+            println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&docker_args));
+            stopped.push(name.to_string());
+            continue;
+        }
+
+        eprintln!("{} Container '{}' has been idle for {}s (idle_timeout {}s), stopping", "(idle)      ".yellow().bold(), name, idle, timeout);
+
+        let succeeded = docker_command(&docker_args, false)
+            .map_err(|e| Error::ContainerStopFailed {
+                name: name.to_string(),
+                reason: e.to_string()
+            })?;
+
+        if succeeded {
+            stopped.push(name.to_string());
+        }
+    }
+
+    Ok(stopped)
+}
+
+fn container_gc(options: GlobalOptions, older_than: Option<u64>) -> Result<bool, Error> {
+    if is_inside_container() {
+        return Err(Error::UnsupportedParameters("'contain gc' cannot run inside a container".to_string()));
+    }
+
+    let stopped = reap_idle_containers(&options, None)?;
+
+    if stopped.is_empty() {
+        println!("{} No idle containers to stop", "(info)      ".blue().bold());
+    } else if !options.dry_run {
+        println!("{} Stopped {} idle container(s)", "(success)".green().bold(), stopped.len());
+    }
+
+    let removed = remove_exited_containers(&options, older_than)?
+        + remove_orphaned_resources(&options, "volume")?
+        + remove_orphaned_resources(&options, "network")?;
+
+    if removed == 0 {
+        println!("{} Nothing left behind to remove", "(info)      ".blue().bold());
+    } else if !options.dry_run {
+        println!("{} Removed {} container(s), volume(s) and network(s)", "(success)".green().bold(), removed);
+    }
+
+    Ok(true)
+}
+
+/// Removes exited containers contain no longer needs: stopped containers whose project directory
+/// no longer exists and, when `older_than` is given, containers kept with --keep (or the `k` flag)
+/// created more than `older_than` seconds ago. Containers without a creation time are never
+/// considered expired. Returns how many were removed.
+fn remove_exited_containers(options: &GlobalOptions, older_than: Option<u64>) -> Result<usize, Error> {
+    let containers = managed_containers(true, &["status=exited".to_string()],
+        &[ROOT_PATH_LABEL, CONFIG_HASH_LABEL, CREATED_AT_LABEL])?;
+    let now = unix_now();
+    let mut removed = 0;
+
+    for container in &containers {
+        let name = container.name();
+        let root_path = container.label(ROOT_PATH_LABEL);
+        let background = !container.label(CONFIG_HASH_LABEL).is_empty();
+        let created_at = container.label(CREATED_AT_LABEL).parse::<u64>().ok().filter(|t| *t > 0);
+
+        let orphaned = !root_path.is_empty() && !Path::new(root_path).exists();
+        let expired = !background && match (older_than, created_at) {
+            (Some(older_than), Some(created_at)) => now.saturating_sub(created_at) >= older_than,
+            _ => false,
+        };
+
+        if !orphaned && !expired {
+            continue;
+        }
+
+        let docker_args = vec!["rm", name];
+
+        if options.dry_run {
+            println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&docker_args));
+            removed += 1;
+            continue;
+        }
+
+        println!("{} container {} ({})", "(removing)  ".yellow().bold(), name,
+            if orphaned { "project is gone" } else { "kept" });
+
+        let succeeded = docker_command(&docker_args, false)
+            .map_err(|e| Error::CommandError {
+                cmd: format!("docker rm {}", name),
+                reason: e.to_string()
+            })?;
+
+        if succeeded {
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Removes the volumes or networks (`kind`) labelled with a project directory that no longer
+/// exists. Ones still in use are reported and left alone. Returns how many were removed.
+fn remove_orphaned_resources(options: &GlobalOptions, kind: &str) -> Result<usize, Error> {
+    let mut removed = 0;
+
+    for (name, root_path) in project_resources(kind, None)? {
+        if root_path.is_empty() || Path::new(&root_path).exists() {
+            continue;
+        }
+
+        let docker_args = vec![kind, "rm", name.as_str()];
+
+        if options.dry_run {
+            println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&docker_args));
+            removed += 1;
+            continue;
+        }
+
+        println!("{} {} {} (project {} is gone)", "(removing)  ".yellow().bold(), kind, name, root_path);
+
+        let succeeded = docker_command(&docker_args, true)
+            .map_err(|e| Error::CommandError {
+                cmd: format!("docker {} rm {}", kind, name),
+                reason: e.to_string()
+            })?;
+
+        if succeeded {
+            removed += 1;
+        } else {
+            println!("{} Could not remove {} '{}', it is probably still in use", "(warning)   ".yellow().bold(), kind, name);
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Directory holding shims: project-local `.contain/bin`, or a shared per-user directory.
+fn shims_dir(global: bool, overrides: &ConfigOverrides) -> Result<PathBuf, Error> {
+    if global {
+        let base = match env::var("XDG_DATA_HOME") {
+            Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var("HOME")
+                .map_err(|_| Error::PathError("HOME is not set".to_string()))?)
+                .join(".local/share"),
+        };
+        return Ok(base.join("contain/shims"));
+    }
+
+    let current_path = env::current_dir()
+        .map_err(|e| Error::PathError(format!("Failed to get current directory: {}", e)))?;
+    let (root_path, _) = find_config_file(current_path, overrides)?;
+
+    Ok(root_path.join(SHIMS_DIR))
+}
+
+fn is_shim(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .map(|contents| contents.lines().nth(1) == Some(SHIM_MARKER))
+        .unwrap_or(false)
+}
+
+fn shims_install(options: GlobalOptions, global: bool) -> Result<bool, Error> {
+    let current_path = env::current_dir()
+        .map_err(|e| Error::PathError(format!("Failed to get current directory: {}", e)))?;
+    let (root_path, config) = find_config_file(current_path, &options.overrides)?;
+
+    println!("{} {}", "(configuration)".blue().bold(), config_file_path(&root_path, &options.overrides).display());
+
+    let commands = get_config_commands(&config);
+    if commands.is_empty() {
+        println!("{} No commands to shim, entries with 'commands: any' cannot be shimmed", "(info)      ".blue().bold());
+        return Ok(true);
+    }
+
+    let dir = shims_dir(global, &options.overrides)?;
+    let contain_path = env::current_exe()
+        .map_err(|e| Error::PathError(format!("Failed to locate the contain binary: {}", e)))?;
+
+    if !options.dry_run {
+        fs::create_dir_all(&dir)
+            .map_err(|e| Error::PathError(format!("Failed to create {}: {}", dir.display(), e)))?;
+    }
+
+    for command in &commands {
+        if !is_valid_shim_name(command) {
+            println!("{} Skipping '{}', not a valid command name", "(warning)   ".yellow().bold(), command);
+            continue;
+        }
+
+        let shim_path = dir.join(command);
+
+        if shim_path.exists() && !is_shim(&shim_path) {
+            println!("{} Skipping '{}', file exists and was not generated by contain", "(warning)   ".yellow().bold(), shim_path.display());
+            continue;
+        }
+
+        if options.dry_run {
+            println!("{} write {}", "(dry run)      ".yellow().bold(), shim_path.display());
+            continue;
+        }
+
+        let contents = format!("#!/bin/sh\n{}\nexec {} run {} \"$@\"\n", SHIM_MARKER,
+            shell_quote(&contain_path.to_string_lossy()), shell_quote(command));
+
+        fs::write(&shim_path, contents)
+            .map_err(|e| Error::PathError(format!("Failed to write {}: {}", shim_path.display(), e)))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&shim_path, fs::Permissions::from_mode(0o755))
+                .map_err(|e| Error::PathError(format!("Failed to make {} executable: {}", shim_path.display(), e)))?;
+        }
+
+        println!("{} {}", "(shim)      ".green().bold(), shim_path.display());
+    }
+
+    // Drop shims for commands that are no longer configured. The global directory is shared
+    // with other projects, so shims there are only ever removed with 'contain shims remove'.
+    if !global
+        && let Ok(entries) = fs::read_dir(&dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let stale = path.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| !commands.iter().any(|c| c == n));
+
+            if stale && is_shim(&path) {
+                if options.dry_run {
+                    println!("{} remove {}", "(dry run)      ".yellow().bold(), path.display());
+                } else {
+                    let _ = fs::remove_file(&path);
+                    println!("{} {}", "(removed)   ".green().bold(), path.display());
+                }
+            }
+        }
+    }
+
+    if global {
+        println!("{} Add {} to your PATH", "(hint)      ".blue().bold(), dir.display());
+    } else {
+        println!("{} Add {} to your PATH, or use 'eval \"$(contain hook bash)\"' to do it on cd", "(hint)      ".blue().bold(), dir.display());
+    }
+
+    Ok(true)
+}
+
+/// Command names that are safe to use as a file name and inside the generated script.
+fn is_valid_shim_name(command: &str) -> bool {
+    !command.is_empty()
+        && command != "." && command != ".."
+        && command.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '+' | '-'))
+}
+
+/// Quotes a value for a POSIX shell script.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn shims_remove(options: GlobalOptions, global: bool) -> Result<bool, Error> {
+    let dir = shims_dir(global, &options.overrides)?;
+
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(_) => {
+            println!("{} No shims found in {}", "(info)      ".blue().bold(), dir.display());
+            return Ok(true);
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !is_shim(&path) {
+            continue;
+        }
+
+        if options.dry_run {
+            println!("{} remove {}", "(dry run)      ".yellow().bold(), path.display());
+        } else {
+            fs::remove_file(&path)
+                .map_err(|e| Error::PathError(format!("Failed to remove {}: {}", path.display(), e)))?;
+            println!("{} {}", "(removed)   ".green().bold(), path.display());
+        }
+    }
+
+    // Only succeeds if nothing but shims lived there
+    if !options.dry_run {
+        let _ = fs::remove_dir(&dir);
+        if !global && let Some(parent) = dir.parent() {
+            let _ = fs::remove_dir(parent);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Prints a hook for the given shell that puts the shims of the nearest project on PATH.
+fn print_shell_hook(shell: &str) {
+    // Whether $dir holds a configuration, checked like config discovery does. Runs on every
+    // prompt, so manifests are only grepped for the embedded table rather than parsed.
+    let found = |or: &str| CONFIG_FILENAMES.iter()
+        .map(|name| format!(r#"test -f "$dir/{}""#, name))
+        .chain(EMBEDDED_CONFIG_SOURCES.iter().map(|(name, keys)| format!(r#"grep -qsE '{}' "$dir/{}""#,
+            keys.iter().map(|key| embedded_config_pattern(name, key)).collect::<Vec<_>>().join("|"), name)))
+        .collect::<Vec<_>>()
+        .join(or);
+
+    let posix_function = format!(r#"_contain_hook() {{
+  local dir="$PWD" shims=""
+  while [ -n "$dir" ]; do
+    if {posix_found}; then
+      [ -d "$dir/{shims}" ] && shims="$dir/{shims}"
+      break
+    fi
+    dir="${{dir%/*}}"
+  done
+  if [ "$shims" != "${{_CONTAIN_SHIMS:-}}" ]; then
+    if [ -n "${{_CONTAIN_SHIMS:-}}" ]; then
+      PATH=":$PATH:"
+      PATH="${{PATH//:$_CONTAIN_SHIMS:/:}}"
+      PATH="${{PATH#:}}"
+      PATH="${{PATH%:}}"
+    fi
+    [ -n "$shims" ] && PATH="$shims:$PATH"
+    _CONTAIN_SHIMS="$shims"
+  fi
+}}
+"#, posix_found = found(" || "), shims = SHIMS_DIR);
+
+    match shell {
+        "bash" => {
+            print!("{}", posix_function);
+            println!(r#"if [[ ";${{PROMPT_COMMAND:-}};" != *";_contain_hook;"* ]]; then"#);
+            println!(r#"  PROMPT_COMMAND="_contain_hook${{PROMPT_COMMAND:+;$PROMPT_COMMAND}}""#);
+            println!("fi");
+        }
+        "zsh" => {
+            print!("{}", posix_function);
+            println!("autoload -Uz add-zsh-hook");
+            println!("add-zsh-hook chpwd _contain_hook");
+            println!("_contain_hook");
+        }
+        "fish" => {
+            print!(r#"function _contain_hook --on-variable PWD
+    set -l dir $PWD
+    set -l shims ""
+    while test -n "$dir"
+        if {fish_found}
+            test -d "$dir/{shims}"; and set shims "$dir/{shims}"
+            break
+        end
+        test "$dir" = "/"; and break
+        set dir (dirname "$dir")
+    end
+    if test "$shims" != "$_CONTAIN_SHIMS"
+        if test -n "$_CONTAIN_SHIMS"; and set -l idx (contains -i -- $_CONTAIN_SHIMS $PATH)
+            set -e PATH[$idx]
+        end
+        test -n "$shims"; and set -gx PATH $shims $PATH
+        set -g _CONTAIN_SHIMS $shims
+    end
+end
+_contain_hook
+"#, fish_found = found("; or "), shims = SHIMS_DIR);
+        }
+        _ => unreachable!()
+    }
+}
+
+/// An extended regular expression matching where `manifest` embeds the table `key`: a TOML
+/// table header such as `[package.metadata.contain]` or `[[package.metadata.contain.images]]`,
+/// or a JSON key.
+fn embedded_config_pattern(manifest: &str, key: &str) -> String {
+    if manifest.ends_with(".json") {
+        format!(r#""{}"[[:space:]]*:"#, key)
+    } else {
+        format!(r"^\[\[?{}[].]", key.replace('.', r"\."))
+    }
+}
+
+/// Lists the commands routed by every config from the current directory upwards,
+/// mirroring the order in which load_config searches them.
+fn routed_commands(overrides: &ConfigOverrides) -> Vec<String> {
+    let mut commands: Vec<String> = Vec::new();
+    let mut path = match env::current_dir() {
+        Ok(p) => p,
+        Err(_) => return commands,
+    };
+
+    while let Ok((root_path, config)) = find_config_file(path, overrides) {
+        for command in get_config_commands(&config) {
+            if !commands.contains(&command) {
+                commands.push(command);
+            }
+        }
+
+        path = root_path;
+        if !path.pop() {
+            break;
+        }
+    }
+
+    commands
+}
+
+fn print_completions(shell: &str) {
+    let target = match shell {
+        "bash" => Shell::Bash,
+        "zsh" => Shell::Zsh,
+        "fish" => Shell::Fish,
+        _ => unreachable!()
+    };
+
+    let mut buffer: Vec<u8> = Vec::new();
+    build_cli().gen_completions_to("contain", target, &mut buffer);
+    let script = String::from_utf8_lossy(&buffer).to_string();
+
+    // Complete the first argument of 'contain run' with the commands routed by the nearest config
+    match target {
+        Shell::Bash => {
+            print!("{}", script);
+            println!(r#"
+_contain_run() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}" prev2="${{COMP_WORDS[COMP_CWORD-2]:-}}"
+    if [[ "$prev" == "run" || (( "$prev" == "-i" || "$prev" == "--interactive" ) && "$prev2" == "run") ]]; then
+        COMPREPLY=( $(compgen -W "$(contain complete-commands 2>/dev/null)" -- "$cur") )
+        return 0
+    fi
+    _contain "$@"
+}}
+
+complete -F _contain_run -o bashdefault -o default contain"#);
+        }
+        Shell::Zsh => {
+            let script = script
+                .replace(":command -- Command and arguments to run:_files'", ":command -- Command and arguments to run:_contain_routed_commands'")
+                .replace("\n_contain \"$@\"", r#"
+(( $+functions[_contain_routed_commands] )) ||
+_contain_routed_commands() {
+    local -a routed
+    routed=(${(f)"$(contain complete-commands 2>/dev/null)"})
+    _describe -t commands 'configured commands' routed
+}
+
+_contain "$@""#);
+            print!("{}", script);
+        }
+        Shell::Fish => {
+            print!("{}", script);
+            println!(r#"complete -c contain -n "__fish_seen_subcommand_from run" -f -a "(contain complete-commands 2>/dev/null)""#);
+        }
+        _ => unreachable!()
+    }
+}
+
+/// Derives a docker-friendly name (lowercase, [a-z0-9_.-]) from the project directory.
+fn project_slug(path: &Path) -> String {
+    let name = path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    let slug: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' { c } else { '-' })
+        .collect();
+    let slug = slug.trim_start_matches(|c: char| !c.is_ascii_alphanumeric()).to_string();
+
+    if slug.is_empty() { "project".to_string() } else { slug }
+}
+
+fn render_init_config(template: &InitTemplate, project: &str) -> String {
+    let mut yaml = String::new();
+
+    yaml.push_str("images:\n");
+    yaml.push_str(&format!("  - image: \"{}-dev:latest\"\n", project));
+    yaml.push_str(&format!("    name: {}-dev\n", project));
+    yaml.push_str(&format!("    dockerfile: {}\n", INIT_DOCKERFILE));
+    yaml.push_str("    commands:\n");
+    for command in template.commands {
+        yaml.push_str(&format!("      - {}\n", command));
+    }
+    yaml.push_str("    env:\n");
+    for item in template.env {
+        yaml.push_str(&format!("      - \"{}\"\n", item));
+    }
+    if !template.ports.is_empty() {
+        yaml.push_str("    ports:\n");
+        for port in template.ports {
+            yaml.push_str(&format!("      - \"{}\"\n", port));
+        }
+    }
+    yaml.push_str("    mounts:\n");
+    yaml.push_str("      # Package caches survive container restarts\n");
+    yaml.push_str("      - type: volume\n");
+    yaml.push_str(&format!("        src: {}-cache\n", project));
+    yaml.push_str("        dst: /cache\n");
+
+    yaml
+}
+
+fn render_init_dockerfile(template: &InitTemplate) -> String {
+    format!(r#"FROM {}
+
+# contain start
+ARG uid
+ARG gid
+ARG username
+ARG workdir_path
+
+# Run as the host user so files written to the workdir keep sane permissions.
+# The base image may already have a user or group with these ids, reuse it if so.
+RUN if ! getent group $gid >/dev/null; then groupadd -g $gid -r $username; fi  && if ! getent passwd $uid >/dev/null; then useradd --no-log-init -m -u $uid -r -g $gid $username; fi
+
+# Owned by the user so the cache volume mounted here is writable
+RUN mkdir -p /cache && chown $uid:$gid /cache
+
+WORKDIR $workdir_path
+# contain end
+"#, template.base_image)
+}
+
+fn contain_init(options: GlobalOptions, template_name: Option<&str>, force: bool) -> Result<bool, Error> {
+    let current_path = env::current_dir()
+        .map_err(|e| Error::PathError(format!("Failed to get current directory: {}", e)))?;
+
+    let template = match template_name {
+        Some(name) => INIT_TEMPLATES.iter()
+            .find(|t| t.name == name)
+            .ok_or_else(|| Error::UnsupportedParameters(format!("Unknown template '{}'", name)))?,
+        None => INIT_TEMPLATES.iter()
+            .find(|t| t.markers.iter().any(|m| current_path.join(m).exists()))
+            .ok_or_else(|| Error::UnsupportedParameters(format!(
+                "Could not detect the project type, use --template with one of: {}",
+                INIT_TEMPLATES.iter().map(|t| t.name).collect::<Vec<_>>().join(", ")
+            )))?,
+    };
+
+    let project = project_slug(&current_path);
+    let files = [
+        (current_path.join(CONTAIN_FILENAME), render_init_config(template, &project)),
+        (current_path.join(INIT_DOCKERFILE), render_init_dockerfile(template)),
+    ];
+
+    // Check everything up front so we never leave a half-initialized project behind
+    if !force
+        && let Some((path, _)) = files.iter().find(|(path, _)| path.exists()) {
+        return Err(Error::FileExists { path: path.display().to_string() });
+    }
+
+    // Another configuration file here would be silently shadowed by the new .contain.yaml
+    let existing = config_file_path(&current_path, &ConfigOverrides::default());
+    if existing != files[0].0 && existing.is_file() {
+        let error = Error::ConfigShadowed { existing: existing.display().to_string(), created: CONTAIN_FILENAME.to_string() };
+        if !force {
+            return Err(error);
+        }
+        eprintln!("{} {}", "(warning)   ".yellow().bold(), error);
+    }
+
+    println!("{} {}", "(template)     ".blue().bold(), template.name);
+
+    for (path, contents) in &files {
+        if options.dry_run {
+            println!("{} write {}", "(dry run)      ".yellow().bold(), path.display());
+            print!("{}", contents);
+            continue;
+        }
+
+        fs::write(path, contents)
+            .map_err(|e| Error::PathError(format!("Failed to write {}: {}", path.display(), e)))?;
+        println!("{} {}", "(created)   ".green().bold(), path.display());
+    }
+
+    println!("{} Use 'contain run {} ...' to run commands in the container", "(hint)      ".blue().bold(), template.commands[0]);
+
+    Ok(true)
+}
+
+/// Lists the containers, images and volumes contain created, across all projects or only the
+/// project in the current directory.
+fn container_ps(options: GlobalOptions, project_only: bool) -> Result<bool, Error> {
+    let mut filters = Vec::new();
+    let mut root_path = None;
+
+    if project_only {
+        let current_path = env::current_dir()
+            .map_err(|e| Error::PathError(format!("Failed to get current directory: {}", e)))?;
+        let (root, _) = find_config_file(current_path, &options.overrides)?;
+        println!("{} {}", "(configuration)".blue().bold(), config_file_path(&root, &options.overrides).display());
+        filters.push(format!("label={}={}", ROOT_PATH_LABEL, root.display()));
+        root_path = Some(root);
+    }
+
+    if options.dry_run {
+        let filter_args: String = filters.iter().map(|f| format!(" --filter {}", f)).collect();
+        let project_filter = project_filter(root_path.as_deref());
+        println!("{} docker ps -a --filter label={}=true{}", "(dry run)      ".yellow().bold(), MANAGED_LABEL, filter_args);
+        println!("{} docker image ls --filter {}", "(dry run)      ".yellow().bold(), project_filter);
+        println!("{} docker volume ls --filter {}", "(dry run)      ".yellow().bold(), project_filter);
+        return Ok(true);
+    }
+
+    let mut containers = managed_containers(true, &filters, &[ROOT_PATH_LABEL])?;
+    let images = project_images(root_path.as_deref())?;
+    let volumes = project_resources("volume", root_path.as_deref())?;
+
+    if containers.is_empty() && images.is_empty() && volumes.is_empty() {
+        println!("{} Nothing has been created by contain", "(info)      ".blue().bold());
+        return Ok(true);
+    }
+
+    // A project whose directory is gone is what 'contain gc' cleans up
+    let project = |root: &str| {
+        if root.is_empty() || Path::new(root).exists() {
+            root.to_string()
+        } else {
+            format!("{} {}", root, "(gone)".red())
+        }
+    };
 
-    // Extra mounts
-    for item in &c.extra_mounts {
-        docker_args.push("--mount".to_string());
-        docker_args.push(item.clone());
+    if !containers.is_empty() {
+        containers.sort_by(|a, b| a.label(ROOT_PATH_LABEL).cmp(b.label(ROOT_PATH_LABEL)).then(a.name().cmp(b.name())));
+
+        println!();
+        println!("{:<30} {:<24} {:<30} {}", "CONTAINER".bold(), "STATUS".bold(), "IMAGE".bold(), "PROJECT".bold());
+        for container in &containers {
+            let status = format!("{:<24}", container.status);
+            println!("{:<30} {} {:<30} {}",
+                container.name(),
+                if status.starts_with("Up") { status.green().to_string() } else { status },
+                container.image,
+                project(container.label(ROOT_PATH_LABEL)));
+        }
     }
 
-    // Ports (unless skip_ports)
-    if !options.skip_ports {
-        for item in &c.ports {
-            docker_args.push("-p".to_string());
-            docker_args.push(item.clone());
+    if !images.is_empty() {
+        println!();
+        println!("{:<30} {:<14} {:<12} {:>8}  {}", "IMAGE".bold(), "ID".bold(), "BUILT".bold(), "SIZE".bold(), "PROJECT".bold());
+        for image in &images {
+            println!("{:<30} {:<14} {:<12} {:>6}MB  {}",
+                image.image,
+                short_id(&image.id),
+                if image.built_at > 0 { format_age(image.built_at) } else { "-".to_string() },
+                image.size / 1_000_000,
+                project(&image.project));
         }
     }
 
-    // Privileged flag
-    if c.flags.contains(&"privileged".to_string()) {
-        docker_args.push("--privileged".to_string());
+    if !volumes.is_empty() {
+        println!();
+        println!("{:<30} {}", "VOLUME".bold(), "PROJECT".bold());
+        for (name, root) in &volumes {
+            println!("{:<30} {}", name, project(root));
+        }
     }
 
-    // Image
-    docker_args.push(c.image.clone());
+    Ok(true)
+}
 
-    // Idle command to keep container running
-    docker_args.push("sleep".to_string());
-    docker_args.push("infinity".to_string());
+/// Prints the resolved image entries of the nearest configuration, or only the one
+/// routing `command`.
+fn config_show(options: GlobalOptions, command: Option<&str>) -> Result<bool, Error> {
+    let current_path = env::current_dir()
+        .map_err(|e| Error::PathError(format!("Failed to get current directory: {}", e)))?;
 
-    let args_refs: Vec<&str> = docker_args.iter().map(|s| s.as_str()).collect();
+    let overrides = ConfigOverrides { skip_var_commands: true, ..options.overrides };
+    let entries = match command {
+        Some(command) => vec![load_config(current_path, command, &overrides)?],
+        None => load_all_configs(current_path, &overrides)?,
+    };
 
-    if options.dry_run {
-        println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&args_refs));
-        return Ok(true);
+    if let Some(c) = entries.first() {
+        println!("{} {}", "(configuration)".blue().bold(), c.config_file.display());
+        println!("{} {}", "(profile)      ".blue().bold(), c.profile.as_deref().unwrap_or("none"));
     }
 
-    println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(&args_refs));
-
-    let status = Command::new("docker")
-        .args(&args_refs)
-        .status()
-        .map_err(|e| Error::CommandError {
-            cmd: format!("docker {}", args_refs.join(" ")),
-            reason: e.to_string()
-        })?;
-
-    if status.success() {
-        println!("{} Container '{}' is now running in the background", "(success)".green().bold(), name);
-        println!("{} Use 'contain run <command>' or 'contain shell' to execute commands", "(hint)      ".blue().bold());
-        println!("{} Use 'contain down' to stop and remove the container", "(hint)      ".blue().bold());
-        Ok(true)
-    } else {
-        Err(Error::DockerError(format!("Failed to start container '{}'", name)))
+    for c in &entries {
+        println!();
+        print_configuration(c);
     }
+
+    Ok(true)
 }
 
-fn start_stopped_container(name: &str, options: &GlobalOptions) -> Result<bool, Error> {
-    let docker_args = vec!["start", name];
+fn print_configuration(c: &Configuration) {
+    let print_list = |label: &str, items: &[String]| {
+        if !items.is_empty() {
+            println!("  {}", format!("{}:", label).bold());
+            for item in items {
+                println!("    - {}", item);
+            }
+        }
+    };
 
-    if options.dry_run {
-        println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&docker_args));
-        return Ok(true);
+    println!("- {} {}", "image:".bold(), c.image);
+    if c.run_image() != c.image {
+        println!("  {} {}", "run image:".bold(), c.run_image());
     }
+    println!("  {} {}", "dockerfile:".bold(), c.dockerfile_description());
+    if let Some(name) = &c.name {
+        println!("  {} {}", "name:".bold(), name);
+    }
+    println!("  {} {}", "workdir:".bold(), c.workdir_path);
+    print_list("env", &c.env_variables);
+    print_list("mounts", &c.extra_mounts);
+    print_list("ports", &c.ports);
+    print_list("flags", &c.flags);
+}
 
-    println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(&docker_args));
+/// Publishes every configured image, or only the one picked with `--entry`.
+fn push_image(options: GlobalOptions) -> Result<bool, Error> {
+    let current_path = env::current_dir()
+        .map_err(|e| Error::PathError(format!("Failed to get current directory: {}", e)))?;
+    let entries = match options.overrides.entry {
+        Some(_) => vec![load_config(current_path, "any", &options.overrides)?],
+        None => load_all_configs(current_path, &options.overrides)?,
+    };
 
-    let status = Command::new("docker")
-        .args(&docker_args)
-        .status()
-        .map_err(|e| Error::CommandError {
-            cmd: format!("docker start {}", name),
-            reason: e.to_string()
-        })?;
+    if let Some(c) = entries.first() {
+        println!("{} {}", "(configuration)".blue().bold(), c.config_file.display());
+    }
 
-    if status.success() {
-        println!("{} Container '{}' is now running", "(success)".green().bold(), name);
-        println!("{} Use 'contain run <command>' or 'contain shell' to execute commands", "(hint)      ".blue().bold());
-        println!("{} Use 'contain down' to stop and remove the container", "(hint)      ".blue().bold());
-        Ok(true)
-    } else {
-        Err(Error::DockerError(format!("Failed to start container '{}'", name)))
+    for c in &entries {
+        push_entry(&options, c)?;
     }
-}
 
-fn container_down(options: GlobalOptions) -> Result<bool, Error> {
-    let (config, name) = require_named_config("down")?;
+    Ok(true)
+}
 
-    let root_path_str = config.root_path.to_str()
-        .ok_or_else(|| Error::PathError("Root path contains invalid UTF-8".to_string()))?;
+/// Publishes one image under its own tag, each of `tags:` and the content-hash tag that
+/// `pull_image` looks for, building it first if it does not exist locally.
+fn push_entry(options: &GlobalOptions, c: &Configuration) -> Result<(), Error> {
+    if !options.dry_run && !image_exists(&c.image)? && !build_image(c)? {
+        return Err(Error::ImageBuildFailed {
+            image: c.image.clone(),
+            dockerfile: c.dockerfile_description()
+        });
+    }
 
-    println!("{} {}/.contain.yaml", "(configuration)".blue().bold(), root_path_str);
+    // Use the hash the image was actually built with, falling back to the current inputs
+    let hash = match options.dry_run {
+        true => None,
+        false => image_label(&c.image, BUILD_HASH_LABEL)?,
+    }.unwrap_or_else(|| build_hash(c));
 
-    // Check for passthrough mode
-    if is_inside_container() {
-        return Err(Error::UnsupportedParameters("'contain down' cannot run inside a container".to_string()));
+    if hash != build_hash(c) {
+        println!("{} {} is out of date with the Dockerfile, it will not be preferred when pulling",
+            "(warning)   ".yellow().bold(), c.image);
     }
 
-    // Check if container is running or stopped
-    let is_running = !options.dry_run && container_exists(&name)?;
-    let is_stopped = !options.dry_run && !is_running && container_is_stopped(&name)?;
-
-    if !options.dry_run && !is_running && !is_stopped {
-        println!("{} Container '{}' does not exist", "(info)      ".blue().bold(), &name);
-        return Ok(true);
+    let mut targets = vec![c.remote_image()];
+    for tag in &c.push_tags {
+        targets.push(format!("{}:{}", c.remote_repository(), tag));
     }
+    targets.push(c.content_hash_image(&hash));
 
-    // Stop the container if running
-    if is_running || options.dry_run {
-        let stop_args = vec!["stop", name.as_str()];
+    for target in &targets {
+        let mut commands: Vec<Vec<&str>> = Vec::new();
+        if target != &c.image {
+            commands.push(vec!["tag", c.image.as_str(), target.as_str()]);
+        }
+        commands.push(vec!["push", target.as_str()]);
 
-        if options.dry_run {
-            println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&stop_args));
-        } else {
-            println!("{} Stopping container '{}'...", "(stopping)  ".yellow().bold(), &name);
-            println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(&stop_args));
+        for docker_args in &commands {
+            if options.dry_run {
+                println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(docker_args));
+                continue;
+            }
+
+            println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(docker_args));
 
             let status = Command::new("docker")
-                .args(&stop_args)
+                .args(docker_args)
                 .status()
-                .map_err(|e| Error::ContainerStopFailed {
-                    name: name.clone(),
+                .map_err(|e| Error::CommandError {
+                    cmd: format!("docker {}", docker_args.join(" ")),
                     reason: e.to_string()
                 })?;
 
             if !status.success() {
-                return Err(Error::ContainerStopFailed {
-                    name: name.clone(),
-                    reason: "docker stop returned non-zero exit code".to_string()
-                });
+                return Err(Error::DockerError(format!("Failed to push {}", target)));
             }
-
-            println!("{} Container '{}' stopped", "(stopped)   ".green().bold(), &name);
         }
     }
 
-    // Remove the container
-    let rm_args = vec!["rm", name.as_str()];
+    if !options.dry_run {
+        println!("{} Pushed {}", "(success)".green().bold(), targets.join(", "));
+    }
 
-    if options.dry_run {
-        println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&rm_args));
-    } else {
-        println!("{} Removing container '{}'...", "(removing)  ".yellow().bold(), &name);
-        println!("{} docker {}", "(executing)    ".bright_blue().bold(), format_docker_args(&rm_args));
+    Ok(())
+}
 
-        let status = Command::new("docker")
-            .args(&rm_args)
-            .status()
-            .map_err(|e| Error::ContainerRemoveFailed {
-                name: name.clone(),
-                reason: e.to_string()
-            })?;
+struct ProjectImage {
+    id: String,
+    tags: Vec<String>,
+    image: String,
+    built_at: u64,
+    size: u64,
+    project: String,
+}
 
-        if !status.success() {
-            return Err(Error::ContainerRemoveFailed {
-                name: name.clone(),
-                reason: "docker rm returned non-zero exit code".to_string()
-            });
+/// Filters docker objects down to those labelled as belonging to the project at `root_path`,
+/// or to any project.
+fn project_filter(root_path: Option<&Path>) -> String {
+    match root_path {
+        Some(root_path) => format!("label={}={}", ROOT_PATH_LABEL, root_path.display()),
+        None => format!("label={}", ROOT_PATH_LABEL),
+    }
+}
+
+/// Lists images labelled as built for the project at `root_path` (or for any project), newest
+/// first, through the Engine API when it can be reached and `docker image ls` otherwise.
+fn project_images(root_path: Option<&Path>) -> Result<Vec<ProjectImage>, Error> {
+    let filter = project_filter(root_path);
+
+    let listed: Vec<String> = match engine_list::<Vec<ImageSummary>>("/images/json", std::slice::from_ref(&filter)) {
+        Some(found) => found?.into_iter().map(|image| image.id).collect(),
+        None => {
+            let result = Command::new("docker")
+                .args(["image", "ls", "-q", "--no-trunc", "--filter", &filter])
+                .output()
+                .map_err(|e| Error::CommandError {
+                    cmd: format!("docker image ls --filter {}", filter),
+                    reason: e.to_string()
+                })?;
+
+            if !result.status.success() {
+                return Err(Error::DockerError("Failed to list images".to_string()));
+            }
+
+            String::from_utf8_lossy(&result.stdout).lines().map(String::from).collect()
         }
+    };
 
-        println!("{} Container '{}' removed", "(removed)   ".green().bold(), &name);
+    // An image is listed once per tag by the CLI
+    let mut ids: Vec<String> = Vec::new();
+    for id in listed {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
     }
 
-    Ok(true)
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let references: Vec<&str> = ids.iter().map(String::as_str).collect();
+    let mut images: Vec<ProjectImage> = docker_inspect::<ImageInspect>("image", &references)?
+        .into_iter()
+        .map(|info| ProjectImage {
+            tags: info.repo_tags.clone().unwrap_or_default(),
+            image: info.config.label(IMAGE_LABEL).unwrap_or_default().to_string(),
+            built_at: info.config.label(BUILT_AT_LABEL).and_then(|t| t.parse().ok()).unwrap_or(0),
+            size: info.size,
+            project: info.config.label(ROOT_PATH_LABEL).unwrap_or_default().to_string(),
+            id: info.id,
+        })
+        .collect();
+
+    images.sort_by_key(|image| std::cmp::Reverse(image.built_at));
+
+    Ok(images)
 }
 
-fn container_status(options: GlobalOptions) -> Result<bool, Error> {
-    let (config, name) = require_named_config("status")?;
+/// Lists the volumes or networks (`kind`) labelled as belonging to the project at `root_path`,
+/// or to any project, as (name, project root) pairs, through the Engine API when it can be
+/// reached and `docker <kind> ls` otherwise.
+fn project_resources(kind: &str, root_path: Option<&Path>) -> Result<Vec<(String, String)>, Error> {
+    let filter = project_filter(root_path);
 
-    let root_path_str = config.root_path.to_str()
-        .ok_or_else(|| Error::PathError("Root path contains invalid UTF-8".to_string()))?;
+    let listed = match kind {
+        "volume" => engine_list::<VolumeList>("/volumes", std::slice::from_ref(&filter)).map(|found| found.map(|list| list.volumes)),
+        _ => engine_list::<Vec<ResourceSummary>>(&format!("/{}s", kind), std::slice::from_ref(&filter)),
+    };
+    if let Some(found) = listed {
+        return Ok(found?.into_iter()
+            .map(|resource| {
+                let project = resource.labels.get(ROOT_PATH_LABEL).cloned().unwrap_or_default();
+                (resource.name, project)
+            })
+            .collect());
+    }
 
-    println!("{} {}/.contain.yaml", "(configuration)".blue().bold(), root_path_str);
-    println!();
+    let format = format!("{{{{.Name}}}}\t{{{{.Label \"{}\"}}}}", ROOT_PATH_LABEL);
 
-    // Check for passthrough mode
-    if is_inside_container() {
-        println!("{} Running inside container (passthrough mode)", "(status)    ".blue().bold());
-        return Ok(true);
+    let result = Command::new("docker")
+        .args([kind, "ls", "--filter", &filter, "--format", &format])
+        .output()
+        .map_err(|e| Error::CommandError {
+            cmd: format!("docker {} ls --filter {}", kind, filter),
+            reason: e.to_string()
+        })?;
+
+    if !result.status.success() {
+        return Err(Error::DockerError(format!("Failed to list {}s", kind)));
+    }
+
+    Ok(String::from_utf8_lossy(&result.stdout)
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(name, project)| (name.to_string(), project.to_string()))
+        .collect())
+}
+
+/// Shortens a docker RFC 3339 timestamp such as `2024-05-01T10:00:00.123456789Z` to the second.
+fn format_timestamp(timestamp: &str) -> String {
+    match timestamp.get(..19) {
+        Some(seconds) => format!("{} UTC", seconds.replace('T', " ")),
+        None => timestamp.to_string(),
+    }
+}
+
+fn format_age(timestamp: u64) -> String {
+    let secs = unix_now().saturating_sub(timestamp);
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
     }
+}
+
+fn short_id(id: &str) -> &str {
+    let id = id.strip_prefix("sha256:").unwrap_or(id);
+    &id[..id.len().min(12)]
+}
+
+fn images_ls(options: GlobalOptions) -> Result<bool, Error> {
+    let current_path = env::current_dir()
+        .map_err(|e| Error::PathError(format!("Failed to get current directory: {}", e)))?;
+    let (root_path, _) = find_config_file(current_path, &options.overrides)?;
+
+    println!("{} {}", "(configuration)".blue().bold(), config_file_path(&root_path, &options.overrides).display());
 
     if options.dry_run {
-        println!("{} docker ps -a -f name={} --format ...", "(dry run)      ".yellow().bold(), &name);
+        println!("{} docker image ls --filter label={}={}", "(dry run)      ".yellow().bold(), ROOT_PATH_LABEL, root_path.display());
         return Ok(true);
     }
 
-    // Get container info
-    match get_container_info(&name)? {
-        Some(info) => {
-            println!("{}", "Container Status".bold());
-            println!("{}", "=".repeat(50));
-            println!("{:<15} {}", "Name:".bold(), info.name);
-            println!("{:<15} {}", "Image:".bold(), info.image);
-            println!("{:<15} {}",
-                "Status:".bold(),
-                if info.running {
-                    info.status.green().to_string()
-                } else {
-                    info.status.red().to_string()
-                });
-            println!("{:<15} {}", "Created:".bold(), info.created);
-            if !info.ports.is_empty() {
-                println!("{:<15} {}", "Ports:".bold(), info.ports);
-            }
-            println!();
+    let images = project_images(Some(&root_path))?;
 
-            if info.running {
-                println!("{} Use 'contain run <command>' or 'contain shell' to execute commands", "(hint)      ".blue().bold());
-                println!("{} Use 'contain down' to stop the container", "(hint)      ".blue().bold());
-            } else {
-                println!("{} Use 'contain up' to start the container", "(hint)      ".blue().bold());
-                println!("{} Use 'contain down' to remove the stopped container", "(hint)      ".blue().bold());
-            }
+    if images.is_empty() {
+        println!("{} No images have been built for this project", "(info)      ".blue().bold());
+        return Ok(true);
+    }
+
+    println!();
+    println!("{:<30} {:<14} {:<12} {:>8}  {}", "IMAGE".bold(), "ID".bold(), "BUILT".bold(), "SIZE".bold(), "TAGS".bold());
+    for image in &images {
+        println!("{:<30} {:<14} {:<12} {:>6}MB  {}",
+            image.image,
+            short_id(&image.id),
+            if image.built_at > 0 { format_age(image.built_at) } else { "-".to_string() },
+            image.size / 1_000_000,
+            if image.tags.is_empty() { "<none>".to_string() } else { image.tags.join(", ") });
+    }
+
+    Ok(true)
+}
+
+/// Removes all but the `keep` newest builds of each configured image. An image still carrying
+/// the tag it was built as is never removed, since that is what contain runs.
+fn images_prune(options: GlobalOptions, keep: usize) -> Result<bool, Error> {
+    let current_path = env::current_dir()
+        .map_err(|e| Error::PathError(format!("Failed to get current directory: {}", e)))?;
+    let (root_path, _) = find_config_file(current_path, &options.overrides)?;
+
+    println!("{} {}", "(configuration)".blue().bold(), config_file_path(&root_path, &options.overrides).display());
+
+    let images = project_images(Some(&root_path))?;
+    let mut kept: HashMap<String, usize> = HashMap::new();
+    let mut removed = 0;
+
+    for image in &images {
+        let count = kept.entry(image.image.clone()).or_insert(0);
+        let current = image.tags.iter().any(|t| t == &image.image);
+
+        if *count < keep || current {
+            *count += 1;
+            continue;
         }
-        None => {
-            println!("{}", "Container Status".bold());
-            println!("{}", "=".repeat(50));
-            println!("{:<15} {}", "Name:".bold(), &name);
-            println!("{:<15} {}", "Status:".bold(), "Not created".yellow());
-            println!();
-            println!("{} Use 'contain up' to create and start the container", "(hint)      ".blue().bold());
+
+        let docker_args = vec!["image", "rm", image.id.as_str()];
+
+        if options.dry_run {
+            println!("{} docker {}", "(dry run)      ".yellow().bold(), format_docker_args(&docker_args));
+            continue;
+        }
+
+        println!("{} {} ({}, built {})", "(removing)  ".yellow().bold(), short_id(&image.id), image.image,
+            format_age(image.built_at));
+
+        let succeeded = docker_command(&docker_args, false)
+            .map_err(|e| Error::CommandError {
+                cmd: format!("docker image rm {}", image.id),
+                reason: e.to_string()
+            })?;
+
+        if succeeded {
+            removed += 1;
+        } else {
+            println!("{} Could not remove {}, it may still be used by a container", "(warning)   ".yellow().bold(), short_id(&image.id));
         }
     }
 
+    if !options.dry_run {
+        println!("{} Removed {} image(s)", "(success)".green().bold(), removed);
+    }
+
     Ok(true)
 }
 
@@ -1088,55 +5178,95 @@ fn run_command(command: &str, args: Vec<&str>, options: GlobalOptions) -> Result
         .map_err(|e| Error::PathError(format!("Failed to get current directory: {}", e)))?;
     let path_clone = current_path.clone();
 
-    let c = load_config(path_clone, command)?;
+    let mut c = load_config(path_clone, command, &options.overrides)?;
 
     let root_path_str = c.root_path.to_str()
         .ok_or_else(|| Error::PathError("Root path contains invalid UTF-8".to_string()))?;
 
-    println!("{} {}/.contain.yaml", "(configuration)".blue().bold(), root_path_str);
+    println!("{} {}", "(configuration)".blue().bold(), c.config_file.display());
+    if let Some(profile) = &c.profile {
+        println!("{} {}", "(profile)      ".blue().bold(), profile);
+    }
 
-    let relative_path = current_path.as_path().strip_prefix(root_path_str)
-        .map_err(|_| Error::PathError(format!(
+    // With --config the project may live elsewhere, in which case commands run from its root
+    let relative_path = match current_path.as_path().strip_prefix(root_path_str) {
+        Ok(relative_path) => relative_path,
+        Err(_) if options.overrides.config.is_some() => Path::new(""),
+        Err(_) => return Err(Error::PathError(format!(
             "Current directory '{}' is not under root path '{}'",
             current_path.display(), root_path_str
-        )))?;
+        ))),
+    };
     let relative_path_str = relative_path.to_str()
         .ok_or_else(|| Error::PathError("Relative path contains invalid UTF-8".to_string()))?;
     let absolute_current_path = format!("{}/{}", c.workdir_path, relative_path_str);
     let absolute_current_path_str = absolute_current_path.as_str();
 
-    // Skip image checks for dry run mode
-    if !options.dry_run {
-        // Check if image exists locally
-        if !image_exists(&c.image)? {
-            // Try downloading it
-            if !download_image(&c.image)? {
-                // Otherwise, build it
-                if !build_image(&c.image, &c.dockerfile, &c.root_path, &c.workdir_path, &c.build_args)? {
-                    return Err(Error::ImageBuildFailed {
-                        image: c.image.clone(),
-                        dockerfile: format!("{}/{}", root_path_str, c.dockerfile)
-                    });
+    ensure_image(&mut c, &options)?;
+
+    let mut resolved = None;
+    if !options.dry_run && !options.skip_name
+        && let Some(n) = &c.name {
+        let (name, info) = resolve_container_name(&c, n)?;
+        c.name = Some(name);
+        resolved = Some(info);
+    }
+
+    if let Some(n) = c.name.clone() {
+        // Reuse the inspect done while resolving the name, skip the check for dry run
+        let mut info = match resolved {
+            Some(info) => info.filter(|info| info.config.label(MANAGED_LABEL) == Some("true")),
+            None if options.dry_run => None,
+            None => get_container_info(&n)?,
+        };
+        let mut state = info.as_ref().map(|info| info.state.status);
+
+        match state {
+            Some(ContainerState::Restarting) => {
+                return Err(Error::ContainerRestarting { name: n });
+            }
+            Some(ContainerState::Paused) => {
+                unpause_container(&n, &options)?;
+                state = Some(ContainerState::Running);
+            }
+            // Bring the background container up (or restart a stopped one) and reuse it
+            Some(ContainerState::Created | ContainerState::Exited | ContainerState::Dead) | None
+                if c.autostart && !options.skip_name => {
+                println!("{} Starting background container '{}'", "(autostart)    ".blue().bold(), &n);
+                // A dead or drifted container is replaced, so what was inspected no longer applies
+                if state == Some(ContainerState::Dead) || info.as_ref().is_some_and(|info| container_has_drifted(&c, info, &options)) {
+                    info = None;
                 }
+                start_background_container(&mut c, &n, &options, false, true)?;
+                state = Some(ContainerState::Running);
+            }
+            Some(stopped) if stopped != ContainerState::Running => {
+                // The name is taken, so run alongside the background container without it
+                println!("{} Background container '{}' is {}, running the command in a new container", "(info)      ".blue().bold(), &n, stopped.as_str());
+                println!("{} Use 'contain up' to start it, or set 'autostart: true'", "(hint)      ".blue().bold());
+                c.name = None;
             }
+            _ => {}
         }
-    }
-
-    println!("{} {}", "(using image)  ".blue().bold(), c.image);
 
-    if let Some(n) = c.name.clone() {
-        // Skip container existence check for dry run
-        if !options.dry_run && container_exists(&n)? {
+        if state == Some(ContainerState::Running) {
+            // A container that was only just created matches the configuration anyway; starting or
+            // unpausing one leaves its mounts and labels as inspected
+            if let Some(info) = &info {
+                check_workspace_mount(&c, &n, info)?;
+                if container_has_drifted(&c, info, &options) {
+                    warn_config_drift(&n);
+                }
+            }
             println!("{} {}", "(executing inside existing container)  ".blue().bold(), &n);
             docker_exec(absolute_current_path_str, c, options, n.as_str(), command, args);
             return Ok(true);
-        } else {
-            docker_run(absolute_current_path_str, c, options, command, args);
         }
-    } else {
-        docker_run(absolute_current_path_str, c, options, command, args);
     }
 
+    ensure_volumes(&c, &options)?;
+    docker_run(absolute_current_path_str, c, options, command, args);
+
     Ok(true)
 }
 
@@ -1152,15 +5282,20 @@ fn docker_run(current_dir: &str, c: Configuration, options: GlobalOptions, comma
     ];
 
     let name;
+    let labels = container_labels(&c);
 
-    if let Some(n) = c.name {
-        if ! options.skip_name {
-            name = n;
-            docker_args.push("--name");
-            docker_args.push(name.as_str());
-        }
+    if let Some(n) = c.name.clone()
+        && ! options.skip_name {
+        name = n;
+        docker_args.push("--name");
+        docker_args.push(name.as_str());
     };
 
+    for label in &labels {
+        docker_args.push("--label");
+        docker_args.push(label.as_str());
+    }
+
     if ! options.run_as_root && ! c.flags.contains(&"root".to_string()) {
         docker_args.push("-u");
         docker_args.push(uid_gid.as_str());
@@ -1178,42 +5313,39 @@ fn docker_run(current_dir: &str, c: Configuration, options: GlobalOptions, comma
         docker_args.push("--privileged");
     };
 
+    if let Some(platform) = &c.platform {
+        docker_args.push("--platform");
+        docker_args.push(platform);
+    };
+
     docker_args.push("-w");
     docker_args.push(current_dir);
 
     let all_env_variables = [&c.env_variables[..], &options.cli_env_variables[..]].concat();
 
-    if all_env_variables.len() > 0 {
-        for i in 0..all_env_variables.len() {
-            let item = &all_env_variables[i];
-            docker_args.push("-e");
-            docker_args.push(item.trim());
-        }
+    for item in &all_env_variables {
+        docker_args.push("-e");
+        docker_args.push(item.trim());
     }
 
     // Mount workspace
     docker_args.push("--mount");
     docker_args.push(&mount);
 
-    if c.extra_mounts.len() > 0 {
-        for i in 0..c.extra_mounts.len() {
-            let item = &c.extra_mounts[i];
-            docker_args.push("--mount");
-            docker_args.push(item);
-        }
+    for item in &c.extra_mounts {
+        docker_args.push("--mount");
+        docker_args.push(item);
     }
 
     if ! options.skip_ports {
-        if c.ports.len() > 0 {
-            for i in 0..c.ports.len() {
-                let item = &c.ports[i];
-                docker_args.push("-p");
-                docker_args.push(item);
-            }
+        for item in &c.ports {
+            docker_args.push("-p");
+            docker_args.push(item);
         }
     }
 
-    docker_args.push(&c.image);
+    let image = c.run_image();
+    docker_args.push(&image);
 
     // Binary to execute inside container
     docker_args.push(command);
@@ -1221,7 +5353,7 @@ fn docker_run(current_dir: &str, c: Configuration, options: GlobalOptions, comma
     // Arguments to pass to binary inside container
     docker_args.extend(args);
 
-    return execute_command(options, "docker", docker_args);
+    execute_command(options, "docker", docker_args)
 }
 
 fn docker_exec(current_dir: &str, c: Configuration, options: GlobalOptions, name: &str, command: &str, args: Vec<&str>) {
@@ -1229,6 +5361,10 @@ fn docker_exec(current_dir: &str, c: Configuration, options: GlobalOptions, name
     let gid = get_current_gid();
     let uid_gid = format!("{}:{}", uid, gid);
 
+    if ! options.dry_run {
+        record_container_use(name);
+    }
+
     let mut docker_args :Vec<&str> = vec![
         "exec"
     ];
@@ -1245,12 +5381,9 @@ fn docker_exec(current_dir: &str, c: Configuration, options: GlobalOptions, name
 
     let all_env_variables = [&c.env_variables[..], &options.cli_env_variables[..]].concat();
 
-    if all_env_variables.len() > 0 {
-        for i in 0..all_env_variables.len() {
-            let item = &all_env_variables[i];
-            docker_args.push("-e");
-            docker_args.push(item.trim());
-        }
+    for item in &all_env_variables {
+        docker_args.push("-e");
+        docker_args.push(item.trim());
     }
 
     docker_args.push(name);
@@ -1261,7 +5394,7 @@ fn docker_exec(current_dir: &str, c: Configuration, options: GlobalOptions, name
     // Arguments to pass to binary inside container
     docker_args.extend(args);
 
-    return execute_command(options, "docker", docker_args);
+    execute_command(options, "docker", docker_args)
 }
 
 /// Formats docker arguments into a multi-line string for readable output.
@@ -1274,7 +5407,8 @@ fn format_docker_args(args: &[&str]) -> String {
     // Flags that take a value as the next argument
     let value_flags: &[&str] = &[
         "-e", "-p", "-u", "-w", "-t", "-f",
-        "--name", "--mount", "--build-arg", "--format",
+        "--name", "--mount", "--build-arg", "--format", "--restart", "--since", "--label",
+        "--target", "--cache-from", "--secret", "--platform", "--ssh", "--cache-to", "--progress",
     ];
 
     let mut lines: Vec<String> = Vec::new();
@@ -1320,6 +5454,18 @@ fn format_docker_args(args: &[&str]) -> String {
 fn execute_command(options: GlobalOptions, command: &str, args: Vec<&str>) {
     if ! options.dry_run {
         println!("{} {} {}", "(executing)    ".bright_blue().bold(), command, format_docker_args(&args));
+
+        if command == "docker"
+            && let Some(result) = engine_attached(&args) {
+            match result {
+                Ok(code) => exit(code),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    exit(1)
+                }
+            }
+        }
+
         match Command::new(command)
                        .args(args)
                        .spawn()
@@ -1340,4 +5486,4 @@ fn execute_command(options: GlobalOptions, command: &str, args: Vec<&str>) {
     } else {
         println!("{} {} {}", "(dry run)      ".yellow().bold(), command, format_docker_args(&args));
     }
-}
\ No newline at end of file
+}
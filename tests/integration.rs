@@ -14,16 +14,27 @@ static HELP_SHOULD_CONTAIN: &[&str] = &[
     "up",
     "down",
     "status",
+    "logs",
 ];
  
-static LS_IN_EXAMPLES_MULTIPLE_CONTAINERS: &'static str = "
+static LS_IN_EXAMPLES_MULTIPLE_CONTAINERS: &str = "
 Dockerfile.mvn
 Dockerfile.yarn
 ";
 
-static ERROR_NO_CONFIG_FILE_FOUND: &'static str = "No docker image found for 'ls' in .contain.yaml or any path above
+static ERROR_NO_CONFIG_FILE_FOUND: &str = "No docker image found for 'ls' in any of .contain.yaml, .contain.yml, .contain.toml, .contain.json, contain.yaml, contain.yml, contain.toml, contain.json, Cargo.toml, package.json in this or any parent directory
 ";
 
+/// A state directory of its own for this test process, with a sweep just recorded, so the idle
+/// reaper neither runs against the local docker daemon nor writes to the user's state.
+fn isolated_state_home() -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("contain-test-state-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("contain")).unwrap();
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    std::fs::write(dir.join("contain/last-reap"), now.to_string()).unwrap();
+    dir
+}
+
 #[cfg(test)]
 mod integration {
     use super::*;
@@ -45,7 +56,7 @@ mod integration {
                                         .rev()
                                         .collect();
 
-            return output_sub;
+            output_sub
         }
     }
 
@@ -57,12 +68,14 @@ mod integration {
             .status()
             .expect("failed to execute process");
 
-        assert_eq!(status.success(), true);
+        assert!(status.success());
     }
 
     #[test]
     fn calling_contain_without_args() {
         let output = Command::new(canonicalize("./target/debug/contain").unwrap())
+            .env("XDG_STATE_HOME", isolated_state_home())
+            .env("CONTAIN_DOCKER_CLI", "1")
             .output()
             .expect("failed to execute process");
 
@@ -78,8 +91,9 @@ mod integration {
     #[ignore]  // Requires Docker in PATH - run with `cargo test -- --ignored`
     fn calling_command_through_docker_works() {
         let output = Command::new(canonicalize("./target/debug/contain").unwrap())
-            .args(&["run", "ls"])
+            .args(["run", "ls"])
             .env("CONTAIN_PASSTHROUGH", "0")  // Disable passthrough for testing
+            .env("XDG_STATE_HOME", isolated_state_home())
             .current_dir(canonicalize("examples/multiple-containers").unwrap())
             .output()
             .expect("failed to execute process");
@@ -96,8 +110,10 @@ mod integration {
     fn calling_command_in_path_without_config_yields_error() {
         // Run from temp directory which has no .contain.yaml in its parent chain
         let output = Command::new(canonicalize("./target/debug/contain").unwrap())
-            .args(&["run", "ls"])
+            .args(["run", "ls"])
             .env("CONTAIN_PASSTHROUGH", "0")  // Disable passthrough for testing
+            .env("XDG_STATE_HOME", isolated_state_home())
+            .env("CONTAIN_DOCKER_CLI", "1")
             .current_dir(std::env::temp_dir())
             .output()
             .expect("failed to execute process");
@@ -114,9 +130,17 @@ mod dry_run_tests {
 
     /// Helper to run contain with --dry flag and capture output
     fn run_dry(dir: &Path, args: &[&str]) -> (String, String, bool) {
+        run_dry_with_env(dir, args, &[])
+    }
+
+    fn run_dry_with_env(dir: &Path, args: &[&str], envs: &[(&str, &str)]) -> (String, String, bool) {
         let output = Command::new(canonicalize("./target/debug/contain").unwrap())
             .current_dir(dir)
             .env("CONTAIN_PASSTHROUGH", "0")  // Disable passthrough for testing
+            .env("XDG_STATE_HOME", isolated_state_home())
+            // Never talk to a local daemon through its socket
+            .env("CONTAIN_DOCKER_CLI", "1")
+            .envs(envs.iter().copied())
             .args(args)
             .output()
             .expect("failed to execute contain");
@@ -245,6 +269,21 @@ mod dry_run_tests {
         assert!(stdout.contains("/container-tmp"), "Output should contain custom mount destination path");
     }
 
+    #[test]
+    fn dry_run_creates_labelled_volumes() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-mounts"),
+            &["--dry", "run", "echo", "hello"],
+        );
+        let root = canonicalize("tests/fixtures/with-mounts").unwrap();
+
+        assert!(success);
+        assert!(stdout.contains("docker volume") && stdout.contains("create"), "Named volumes should be created up front. Got: {}", stdout);
+        assert!(stdout.contains(&format!("contain.root-path={}", root.display())), "Volumes should record their project");
+        assert!(stdout.lines().any(|line| line.trim() == "test-cache"), "The configured volume should be created");
+        assert_eq!(stdout.matches("docker volume").count(), 1, "Bind mounts are not volumes");
+    }
+
     #[test]
     fn no_config_file_shows_error() {
         let temp_dir = std::env::temp_dir();
@@ -272,15 +311,1751 @@ mod dry_run_tests {
     }
 
     #[test]
-    fn dry_run_combined_flags() {
+    fn dry_run_up_defaults_to_sleep_infinity() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-name"),
+            &["--dry", "up"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("-d"), "Output should contain '-d' for detached mode");
+        assert!(stdout.contains("sleep"), "Output should contain the idle command");
+        assert!(stdout.contains("infinity"), "Output should contain the idle command args");
+        assert!(!stdout.contains("--restart"), "Output should NOT contain '--restart' without a policy");
+    }
+
+    #[test]
+    fn dry_run_up_uses_up_command_and_restart_policy() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-up-command"),
+            &["--dry", "up"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("--restart unless-stopped"), "Output should contain the restart policy");
+        assert!(stdout.contains("npm"), "Output should contain the up_command");
+        assert!(stdout.contains("dev"), "Output should contain the up_command args");
+        assert!(!stdout.contains("infinity"), "Output should NOT contain the idle command when up_command is set");
+    }
+
+    #[test]
+    fn dry_run_up_labels_container_with_config_hash() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-name"),
+            &["--dry", "up"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("--label contain.config-hash="), "Output should label the container with the config hash");
+    }
+
+    #[test]
+    fn dry_run_named_run_labels_root_path() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-name"),
+            &["--dry", "run", "echo", "hello"],
+        );
+        let root = canonicalize("tests/fixtures/with-name").unwrap();
+
+        assert!(success);
+        assert!(stdout.contains(&format!("contain.root-path={}", root.display())), "Named containers should record their checkout. Got: {}", stdout);
+    }
+
+    #[test]
+    fn dry_run_labels_managed_containers() {
         let (stdout, _, success) = run_dry(
             Path::new("tests/fixtures/basic"),
-            &["--dry", "-k", "run", "-i", "echo", "test"],
+            &["--dry", "run", "echo", "hello"],
         );
+        let root = canonicalize("tests/fixtures/basic").unwrap();
 
         assert!(success);
-        assert!(stdout.contains("-it"), "Output should contain '-it' for interactive");
-        assert!(!stdout.contains("--rm"), "Output should NOT contain '--rm' when -k is used");
+        assert!(stdout.contains("contain.managed=true"), "Containers should be marked as managed. Got: {}", stdout);
+        assert!(stdout.contains(&format!("contain.version={}", env!("CARGO_PKG_VERSION"))), "Containers should record the contain version");
+        assert!(stdout.contains(&format!("contain.config-file={}", root.join(".contain.yaml").display())), "Containers should record their configuration file");
+        assert!(stdout.contains("contain.entry=test-image:latest"), "Containers should record their entry");
+    }
+
+    #[test]
+    fn dry_run_ps_lists_managed_containers() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/basic"),
+            &["--dry", "ps", "--project"],
+        );
+        let root = canonicalize("tests/fixtures/basic").unwrap();
+
+        assert!(success);
+        assert!(stdout.contains("docker ps -a --filter label=contain.managed=true"), "ps should list managed containers. Got: {}", stdout);
+        assert!(stdout.contains(&format!("--filter label=contain.root-path={}", root.display())), "--project should narrow to the current project");
+        assert!(stdout.contains(&format!("docker image ls --filter label=contain.root-path={}", root.display())), "ps should list the project's images");
+        assert!(stdout.contains(&format!("docker volume ls --filter label=contain.root-path={}", root.display())), "ps should list the project's volumes");
+    }
+
+    #[test]
+    fn gc_rejects_invalid_age() {
+        let (_, stderr, success) = run_dry(
+            Path::new("tests/fixtures/basic"),
+            &["--dry", "gc", "--older-than", "a week"],
+        );
+
+        assert!(!success);
+        assert!(stderr.contains("--older-than"), "An invalid age should be reported. Got: {}", stderr);
+    }
+
+    #[test]
+    fn gc_rejects_overflowing_age() {
+        let (_, stderr, success) = run_dry(
+            Path::new("tests/fixtures/basic"),
+            &["--dry", "gc", "--older-than", "999999999999999999d"],
+        );
+
+        assert!(!success);
+        assert!(stderr.contains("--older-than") && !stderr.contains("panicked"), "An overflowing age should be rejected. Got: {}", stderr);
+    }
+
+    #[test]
+    fn dry_run_config_hash_changes_with_config() {
+        let label = |dir: &str| {
+            let (stdout, _, _) = run_dry(Path::new(dir), &["--dry", "up"]);
+            stdout.lines()
+                .find(|line| line.contains("contain.config-hash="))
+                .map(|line| line.trim().to_string())
+        };
+
+        assert!(label("tests/fixtures/with-name").is_some());
+        assert_eq!(label("tests/fixtures/with-name"), label("tests/fixtures/with-name"), "Hash should be stable");
+        assert_ne!(label("tests/fixtures/with-name"), label("tests/fixtures/with-up-command"), "Hash should differ between configs");
+    }
+
+    #[test]
+    fn dry_run_up_recreate_removes_existing_container() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-name"),
+            &["--dry", "up", "--recreate"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("rm"), "Output should remove the existing container");
+        assert!(stdout.contains("-f"), "Output should force removal");
+        assert!(stdout.contains("-d"), "Output should start a new detached container");
+    }
+
+    #[test]
+    fn dry_run_up_labels_idle_timeout_in_seconds() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-idle-timeout"),
+            &["--dry", "up"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("--label contain.idle-timeout=1800"), "Output should label the container with the idle timeout in seconds");
+    }
+
+    #[test]
+    fn dry_run_up_without_idle_timeout_has_no_idle_label() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-name"),
+            &["--dry", "up"],
+        );
+
+        assert!(success);
+        assert!(!stdout.contains("contain.idle-timeout"), "Output should NOT contain an idle timeout label");
+    }
+
+    #[test]
+    fn dry_run_autostart_starts_background_container_and_execs() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-autostart"),
+            &["--dry", "run", "echo", "hello"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("-d"), "Output should start a detached container");
+        assert!(stdout.contains("infinity"), "Output should keep the background container running");
+        assert!(stdout.contains("exec"), "Output should exec into the background container");
+        assert!(!stdout.contains("--rm"), "Output should NOT start a throwaway container");
+        assert_eq!(stdout.matches("(using image)").count(), 1, "The image should be resolved once. Got: {}", stdout);
+    }
+
+    #[test]
+    fn dry_run_autostart_ignored_with_skip_name() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-autostart"),
+            &["--dry", "--skip-name", "run", "echo", "hello"],
+        );
+
+        assert!(success);
+        assert!(!stdout.contains("exec"), "Output should NOT exec when --skip-name is used");
+        assert!(stdout.contains("--rm"), "Output should start a throwaway container");
+    }
+
+    #[test]
+    fn dry_run_user_layer_runs_derived_image() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-user-layer"),
+            &["--dry", "run", "echo", "hello"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("--label contain.base-image-id="), "Output should build the user layer");
+        assert!(stdout.contains("-t alpine:3.20-contain-"), "Output should tag the user layer per uid/gid. Got: {}", stdout);
+        let run_output = &stdout[stdout.find("docker run").expect("Output should contain docker run")..];
+        assert!(run_output.contains("alpine:3.20-contain-"), "Container should run from the derived image");
+    }
+
+    #[test]
+    fn dry_run_user_layer_never_builds_from_a_bare_image_id() {
+        let dir = std::env::temp_dir().join(format!("contain-user-layer-lock-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::copy("tests/fixtures/with-user-layer/.contain.yaml", dir.join(".contain.yaml")).unwrap();
+        std::fs::write(dir.join("Dockerfile"), "FROM alpine:3.20\n").unwrap();
+        let lock = |pinned: &str| format!("images:\n  - image: \"alpine:3.20\"\n    digest: \"{}\"\n", pinned);
+
+        std::fs::write(dir.join(".contain.lock"), lock("sha256:0123456789abcdef")).unwrap();
+        let (stdout, _, success) = run_dry(&dir, &["--dry", "run", "echo", "hello"]);
+        assert!(success);
+        assert!(stdout.contains("--label contain.base-image-id=alpine:3.20"), "A local build pin should build FROM the configured image. Got: {}", stdout);
+
+        std::fs::write(dir.join(".contain.lock"), lock("alpine@sha256:0123456789abcdef")).unwrap();
+        let (stdout, _, success) = run_dry(&dir, &["--dry", "run", "echo", "hello"]);
+        assert!(success);
+        assert!(stdout.contains("--label contain.base-image-id=alpine@sha256:0123456789abcdef"), "A registry digest pin should be built FROM. Got: {}", stdout);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn dry_run_without_user_layer_runs_configured_image() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/basic"),
+            &["--dry", "run", "echo", "hello"],
+        );
+
+        assert!(success);
+        assert!(!stdout.contains("-contain-"), "Output should NOT use a derived image");
+    }
+
+    #[test]
+    fn dry_run_shows_build_options() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-build-options"),
+            &["--dry", "run", "echo", "hello"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("docker build"), "Output should show the build command");
+        assert!(stdout.contains("--target dev"), "Output should contain the build target");
+        assert!(stdout.contains("--cache-from test-image:cache"), "Output should contain the cache source");
+        assert!(stdout.contains("--secret id=token,src=token.txt"), "Output should contain the build secret");
+        assert!(stdout.contains("-f -"), "Inline Dockerfile should be read from stdin");
+        assert!(stdout.contains("tests/fixtures/with-build-options/.."), "Output should use the configured build context");
+        assert!(stdout.contains("| RUN apk add --no-cache make"), "Output should show the inline Dockerfile");
+        assert!(stdout.contains("--label contain.root-path="), "Built image should be labelled with the project root");
+        assert!(stdout.contains("--label contain.build-hash="), "Built image should be labelled with the build hash");
+        assert!(stdout.contains("--label contain.built-at="), "Built image should be labelled with the build time");
+        assert!(stdout.contains("-t test-image:contain-"), "Output should add the content-hash tag. Got: {}", stdout);
+    }
+
+    #[test]
+    fn dry_run_push() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-registry"),
+            &["--dry", "push"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("registry.example.com/team/test-image:latest"), "Output should push the configured tag. Got: {}", stdout);
+        assert!(stdout.contains("registry.example.com/team/test-image:dev"), "Output should push the extra tags");
+        assert!(stdout.contains("registry.example.com/team/test-image:contain-"), "Output should push the content-hash tag");
+        assert!(stdout.contains("docker tag") && stdout.contains("docker push"), "Output should tag the local image and push it");
+    }
+
+    #[test]
+    fn dry_run_push_covers_every_entry() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-commands"),
+            &["--dry", "push"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("node-image:contain-"), "Output should push the first entry. Got: {}", stdout);
+        assert!(stdout.contains("test-image:contain-"), "Output should push the second entry. Got: {}", stdout);
+
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-commands"),
+            &["--dry", "--entry", "test-image:latest", "push"],
+        );
+
+        assert!(success);
+        assert!(!stdout.contains("node-image"), "--entry should push only the selected entry. Got: {}", stdout);
+        assert!(stdout.contains("test-image:contain-"));
+    }
+
+    #[test]
+    fn dry_run_skips_content_hash_pull_without_push_target() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/basic"),
+            &["--dry", "run", "echo", "hello"],
+        );
+
+        assert!(success);
+        assert!(!stdout.contains(":contain-"), "Without a push target no content-hash tag should be pulled. Got: {}", stdout);
+    }
+
+    #[test]
+    fn dry_run_prefers_pushed_build() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-registry"),
+            &["--dry", "run", "echo", "hello"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("is pulled from registry.example.com/team/test-image:contain-"), "Output should prefer the content-hash tag. Got: {}", stdout);
+    }
+
+    #[test]
+    fn dry_run_uses_locked_digest() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-lock"),
+            &["--dry", "run", "echo", "hello"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("is pinned to alpine@sha256:0123456789abcdef"), "Output should use the lockfile. Got: {}", stdout);
+        assert!(!stdout.contains("docker build"), "A pinned image should not be built");
+        assert!(stdout.matches("alpine@sha256:0123456789abcdef").count() >= 2, "The container should run the pinned digest");
+    }
+
+    #[test]
+    fn dry_run_lock_update() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-lock"),
+            &["--dry", "lock", "update"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("docker pull alpine:latest"), "Output should pull the configured images. Got: {}", stdout);
     }
-}
 
+    #[test]
+    fn dry_run_lock_update_locks_resolved_images() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-templates"),
+            &["--dry", "lock", "update"],
+        );
+        assert!(success);
+        assert!(stdout.contains("docker pull with-templates:dev"), "Templates should be expanded. Got: {}", stdout);
+
+        let (stdout, _, success) = run_dry_with_env(
+            Path::new("tests/fixtures/with-profiles"),
+            &["--dry", "--profile", "ci", "lock", "update"],
+            &[("CI", "false")],
+        );
+        assert!(success);
+        assert!(stdout.contains("docker pull test-image:ci"), "The profile should be applied. Got: {}", stdout);
+
+        // What is locked under the resolved image is what the profiled entry is pinned to
+        let dir = std::env::temp_dir().join(format!("contain-lock-profile-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::copy("tests/fixtures/with-profiles/.contain.yaml", dir.join(".contain.yaml")).unwrap();
+        std::fs::write(dir.join("Dockerfile"), "FROM alpine\n").unwrap();
+        std::fs::write(dir.join(".contain.lock"), "images:\n  - image: \"test-image:ci\"\n    digest: \"test-image@sha256:0123456789abcdef\"\n").unwrap();
+
+        let (stdout, _, success) = run_dry_with_env(&dir, &["--dry", "--profile", "ci", "run", "echo", "hello"], &[("CI", "false")]);
+        assert!(success);
+        assert!(stdout.contains("is pinned to test-image@sha256:0123456789abcdef"), "The lock should apply to the profiled image. Got: {}", stdout);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn dry_run_image_override() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/basic"),
+            &["--dry", "--image", "alpine:3.20", "--workdir", "/src", "run", "echo", "hello"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("(using image)") && stdout.contains("alpine:3.20"), "Output should use the overridden image. Got: {}", stdout);
+        assert!(stdout.contains("dst=/src"), "Output should mount the project at the overridden workdir");
+        assert!(!stdout.contains(" test-image:latest \\"), "The configured image should not be run");
+    }
+
+    #[test]
+    fn overrides_are_not_exported_to_commands() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-var"),
+            &["--dry", "--image", "alpine:3.20", "run", "echo", "hello"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("SEEN_IMAGE=unset"), "var: commands should not see --image. Got: {}", stdout);
+
+        // Only the documented CONTAIN_CONFIG and CONTAIN_PROFILE are read from the environment
+        let (stdout, _, success) = run_dry_with_env(
+            Path::new("tests/fixtures/basic"),
+            &["--dry", "run", "echo", "hello"],
+            &[("CONTAIN_IMAGE", "alpine:3.20"), ("CONTAIN_ENTRY", "missing")],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("test-image:latest"), "A stray CONTAIN_IMAGE should be ignored. Got: {}", stdout);
+    }
+
+    #[test]
+    fn dry_run_entry_override() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-commands"),
+            &["--dry", "--entry", "node-image:latest", "run", "cargo", "build"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("node-image:latest"), "Output should use the selected entry. Got: {}", stdout);
+
+        let (_, stderr, success) = run_dry(
+            Path::new("tests/fixtures/with-commands"),
+            &["--dry", "--entry", "missing", "run", "cargo", "build"],
+        );
+
+        assert!(!success);
+        assert!(stderr.contains("'missing'"), "Error should name the missing entry. Got: {}", stderr);
+    }
+
+    #[test]
+    fn dry_run_entry_override_matches_expanded_image() {
+        let (stdout, stderr, success) = run_dry(
+            Path::new("tests/fixtures/with-templates"),
+            &["--dry", "--entry", "with-templates:dev", "run", "echo", "hello"],
+        );
+
+        assert!(success, "--entry should match the expanded image. Got: {}", stderr);
+        assert!(stdout.contains("with-templates:dev"), "Output should use the selected entry. Got: {}", stdout);
+    }
+
+    #[test]
+    fn dry_run_config_hash_covers_cli_env_and_skip_ports() {
+        let dir = std::env::temp_dir().join(format!("contain-config-hash-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(".contain.yaml"), "images:\n  - image: test-image:latest\n    name: test-hash\n    dockerfile: Dockerfile\n    commands: any\n    ports:\n      - \"8080:80\"\n").unwrap();
+        std::fs::write(dir.join("Dockerfile"), "FROM alpine\n").unwrap();
+
+        let hash = |args: &[&str]| {
+            let (stdout, _, success) = run_dry(&dir, args);
+            assert!(success);
+            let start = stdout.find("contain.config-hash=").expect("up should label the config hash");
+            stdout[start..].split_whitespace().next().unwrap().to_string()
+        };
+
+        let plain = hash(&["--dry", "up"]);
+        assert_ne!(plain, hash(&["--dry", "-e", "FOO=1", "up"]), "-e should change the config hash");
+        assert_ne!(plain, hash(&["--dry", "--skip-ports", "up"]), "--skip-ports should change the config hash");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn dry_run_config_override() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests"),
+            &["--dry", "--config", "fixtures/with-commands/.contain.yaml", "run", "cargo", "build"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("fixtures/with-commands/.contain.yaml"), "Output should use the given config file. Got: {}", stdout);
+        assert!(stdout.contains("test-image:latest"), "Output should route the command with the given config file");
+    }
+
+    #[test]
+    fn dry_run_config_from_env() {
+        let (stdout, _, success) = run_dry_with_env(
+            Path::new("tests"),
+            &["--dry", "run", "cargo", "build"],
+            &[("CONTAIN_CONFIG", "fixtures/with-commands/.contain.yaml")],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("fixtures/with-commands/.contain.yaml"), "Output should use CONTAIN_CONFIG. Got: {}", stdout);
+
+        let (_, stderr, success) = run_dry_with_env(
+            Path::new("tests"),
+            &["--dry", "run", "ls"],
+            &[("CONTAIN_CONFIG", "fixtures/with-commands/.contain.yaml")],
+        );
+
+        assert!(!success);
+        assert!(stderr.contains("No docker image found for 'ls' in /") && stderr.contains("fixtures/with-commands/.contain.yaml"),
+            "The error should name the explicit file that was searched. Got: {}", stderr);
+    }
+
+    #[test]
+    fn dry_run_alternative_filename() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-alternative-name"),
+            &["--dry", "run", "echo", "hello"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("with-alternative-name/contain.toml"), "Output should find contain.toml. Got: {}", stdout);
+        assert!(stdout.contains("toml-image:latest"), "Output should use the image from contain.toml");
+    }
+
+    #[test]
+    fn dry_run_embedded_configs() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-cargo-metadata"),
+            &["--dry", "run", "cargo", "build"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("with-cargo-metadata/Cargo.toml"), "Output should use Cargo.toml metadata. Got: {}", stdout);
+        assert!(stdout.contains("cargo-image:latest"), "Output should use the image from Cargo.toml");
+
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-package-json"),
+            &["--dry", "run", "npm", "test"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("with-package-json/package.json"), "Output should use package.json. Got: {}", stdout);
+        assert!(stdout.contains("node-image:latest"), "Output should use the image from package.json");
+    }
+
+    #[test]
+    fn dedicated_config_takes_priority() {
+        // with-alternative-name has both contain.toml and a package.json with a "contain" key
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-alternative-name"),
+            &["--dry", "run", "npm", "test"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("toml-image:latest"), "contain.toml should win over package.json. Got: {}", stdout);
+    }
+
+    #[test]
+    fn dry_run_profiles() {
+        let dir = Path::new("tests/fixtures/with-profiles");
+
+        let (stdout, _, success) = run_dry_with_env(dir, &["--dry", "run", "echo", "hello"], &[("CI", "false")]);
+        assert!(success);
+        assert!(stdout.contains("3000:3000"), "Without a profile the ports should be mapped. Got: {}", stdout);
+        assert!(stdout.contains("MODE=dev"));
+
+        let (stdout, _, success) = run_dry_with_env(dir, &["--dry", "--profile", "ci", "run", "echo", "hello"], &[("CI", "false")]);
+        assert!(success);
+        assert!(!stdout.contains("3000:3000"), "The ci profile should drop the ports. Got: {}", stdout);
+        assert!(stdout.contains("MODE=ci") && !stdout.contains("MODE=dev"), "The ci profile should override MODE");
+        assert!(stdout.contains("test-image:ci"), "The ci profile should change the image tag");
+
+        let (stdout, _, success) = run_dry_with_env(dir, &["--dry", "run", "echo", "hello"], &[("CI", "true")]);
+        assert!(success);
+        assert!(stdout.contains("(profile)") && stdout.contains("test-image:ci"), "CI=true should select the ci profile. Got: {}", stdout);
+
+        let (_, stderr, success) = run_dry(dir, &["--dry", "--profile", "missing", "run", "echo", "hello"]);
+        assert!(!success);
+        assert!(stderr.contains("profile 'missing' is not defined"), "Unknown profiles should be rejected. Got: {}", stderr);
+    }
+
+    #[test]
+    fn config_show_applies_profile() {
+        let (stdout, _, success) = run_dry_with_env(
+            Path::new("tests/fixtures/with-profiles"),
+            &["config", "show", "--profile", "ci"],
+            &[("CI", "false")],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("image: test-image:ci"), "config show should display the resolved image. Got: {}", stdout);
+        assert!(!stdout.contains("3000:3000"), "config show should apply the profile overlay");
+    }
+
+    #[test]
+    fn config_show_does_not_run_var_commands() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-var"),
+            &["config", "show"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("SEEN_IMAGE=$(printenv CONTAIN_IMAGE || echo unset)"), "var: commands should be shown, not run. Got: {}", stdout);
+    }
+
+    #[test]
+    fn dry_run_template_variables() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-templates"),
+            &["--dry", "--skip-name", "run", "echo", "hello"],
+        );
+        let root = canonicalize("tests/fixtures/with-templates").unwrap();
+
+        assert!(success);
+        assert!(stdout.contains("with-templates:dev"), "Image should use project_name and the default tag. Got: {}", stdout);
+        assert!(stdout.contains(&format!("CONFIG_DIR={}", root.display())), "config_dir should be the project root");
+        assert!(stdout.contains("WORKDIR=/workdir"), "workdir should be the container workdir");
+        assert!(stdout.contains(&format!("src={}/cache,dst=/workdir/.cache", root.display())), "Mounts should be expanded");
+        assert!(stdout.contains("GID="), "Build args should be expanded");
+        assert!(!stdout.contains("${"), "No variables should be left unexpanded");
+    }
+
+    #[test]
+    fn dry_run_git_branch_variable() {
+        let dir = std::env::temp_dir().join(format!("contain-git-branch-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(".contain.yaml"), "images:\n  - image: \"test-image:${git_branch:-latest}\"\n    dockerfile: Dockerfile\n    commands: any\n").unwrap();
+        std::fs::write(dir.join("Dockerfile"), "FROM alpine\n").unwrap();
+
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(&dir)
+                .output()
+                .expect("failed to execute git")
+                .status;
+            assert!(status.success(), "git {:?} failed", args);
+        };
+        git(&["init", "-q", "-b", "feature/x"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "initial"]);
+
+        let (stdout, _, success) = run_dry(&dir, &["--dry", "run", "echo", "hello"]);
+        assert!(success);
+        assert!(stdout.contains("test-image:feature-x"), "The branch should be usable as a tag. Got: {}", stdout);
+
+        git(&["checkout", "-q", "--detach"]);
+        let (stdout, _, success) = run_dry(&dir, &["--dry", "run", "echo", "hello"]);
+        assert!(success);
+        assert!(stdout.contains("test-image:latest"), "A detached HEAD should fall back to the default. Got: {}", stdout);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn undefined_variable_is_an_error() {
+        let (_, stderr, success) = run_dry(
+            Path::new("tests/fixtures/with-undefined-variable"),
+            &["--dry", "run", "echo", "hello"],
+        );
+
+        assert!(!success);
+        assert!(stderr.contains("undefined variable 'CONTAIN_TEST_UNDEFINED'"), "Error should name the variable. Got: {}", stderr);
+    }
+
+    #[test]
+    fn discovery_stops_at_boundary() {
+        let dir = Path::new("tests/fixtures/with-discovery-stop/project/sub");
+        let (stdout, _, success) = run_dry(dir, &["--dry", "run", "echo", "hello"]);
+
+        assert!(success);
+        assert!(stdout.contains("outer-image:latest"), "Without a boundary the parent config is used. Got: {}", stdout);
+
+        let boundary = canonicalize("tests/fixtures/with-discovery-stop/project").unwrap();
+        let (_, stderr, success) = run_dry_with_env(
+            dir,
+            &["--dry", "run", "echo", "hello"],
+            &[("CONTAIN_DISCOVERY_STOP", boundary.to_str().unwrap())],
+        );
+
+        assert!(!success);
+        assert!(stderr.contains("No docker image found"), "Discovery should stop at the boundary. Got: {}", stderr);
+    }
+
+    #[test]
+    fn dry_run_buildx_options() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-buildx"),
+            &["--dry", "run", "echo", "hello"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("docker buildx"), "Output should build with buildx. Got: {}", stdout);
+        assert!(stdout.contains("--load"), "buildx output should be loaded into the local image store");
+        assert!(stdout.contains("--ssh default"), "Output should forward the SSH agent");
+        assert!(stdout.contains("--progress plain"), "Output should set the progress mode");
+        assert!(stdout.contains("--cache-from type=local,src="), "Output should import the local cache");
+        assert!(stdout.contains("--cache-to type=local,dest="), "Output should export the local cache");
+        assert_eq!(stdout.matches("--platform linux/arm64").count(), 2, "Both build and run should use the platform");
+    }
+
+    #[test]
+    fn skips_cache_export_on_docker_driver() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // A docker stand-in whose selected buildx builder uses the docker driver and which has no images
+        let bin = std::env::temp_dir().join(format!("contain-buildx-driver-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&bin);
+        std::fs::create_dir_all(&bin).unwrap();
+        let docker = bin.join("docker");
+        std::fs::write(&docker, format!(r#"#!/bin/sh
+echo "$*" >> '{}/calls'
+case "$1 $2" in
+  "buildx inspect") printf 'Name: default\nDriver: docker\n' ;;
+  "image inspect"|"pull "*) exit 1 ;;
+esac
+"#, bin.display())).unwrap();
+        std::fs::set_permissions(&docker, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap_or_default());
+        let calls = || std::fs::read_to_string(bin.join("calls")).unwrap_or_default();
+
+        let (stdout, _, success) = run_dry_with_env(
+            Path::new("tests/fixtures/with-buildx"),
+            &["--dry", "run", "echo", "hello"],
+            &[("PATH", &path)],
+        );
+        assert!(success);
+        assert!(stdout.contains("--cache-to type=local,dest="), "A dry run should show the configured cache export. Got: {}", stdout);
+        assert!(!calls().contains("buildx inspect"), "A dry run should not inspect the builder. Calls: {}", calls());
+
+        let (_, stderr, success) = run_dry_with_env(
+            Path::new("tests/fixtures/with-buildx"),
+            &["run", "echo", "hello"],
+            &[("PATH", &path)],
+        );
+        assert!(success, "run should succeed. Got: {}", stderr);
+        let build = calls().lines().find(|call| call.starts_with("buildx build")).map(String::from)
+            .unwrap_or_else(|| panic!("The image should be built. Calls: {}", calls()));
+        assert!(build.contains("--cache-from type=local,src="), "The build should still import the local cache. Got: {}", build);
+        assert!(!build.contains("--cache-to"), "The build should not export the cache with the docker driver. Got: {}", build);
+        assert!(stderr.contains("docker driver"), "A warning should explain why the export was skipped. Got: {}", stderr);
+
+        let _ = std::fs::remove_dir_all(&bin);
+    }
+
+    #[test]
+    fn images_prune_takes_its_own_keep_last_option() {
+        let (_, stderr, success) = run_dry(
+            Path::new("tests/fixtures/basic"),
+            &["--dry", "images", "prune", "--keep-last=many"],
+        );
+
+        assert!(!success);
+        assert!(stderr.contains("Number of builds to keep must be a number, got 'many'"), "--keep-last should take the count. Got: {}", stderr);
+
+        let (_, _, success) = run_dry(
+            Path::new("tests/fixtures/basic"),
+            &["--dry", "images", "prune", "3"],
+        );
+        assert!(!success, "The count is no longer positional");
+    }
+
+    #[test]
+    fn cache_to_requires_buildx() {
+        let (_, stderr, success) = run_dry(
+            Path::new("tests/fixtures/with-invalid-cache-to"),
+            &["--dry", "run", "echo", "hello"],
+        );
+
+        assert!(!success);
+        assert!(stderr.contains("requires 'builder: buildx'"), "Error should explain that buildx is required. Got: {}", stderr);
+    }
+
+    #[test]
+    fn missing_dockerfile_is_a_config_error() {
+        let (_, stderr, success) = run_dry(
+            Path::new("tests/fixtures/without-dockerfile"),
+            &["--dry", "run", "echo", "hello"],
+        );
+
+        assert!(!success);
+        assert!(stderr.contains("Missing required field 'dockerfile'"), "Error should mention the missing dockerfile. Got: {}", stderr);
+    }
+
+    #[test]
+    fn dry_run_logs_follow() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-up-command"),
+            &["--dry", "logs", "-f"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("logs"), "Output should contain 'logs'");
+        assert!(stdout.contains("-f"), "Output should contain '-f' when following");
+        assert!(stdout.contains("test-service"), "Output should contain the container name");
+    }
+
+    #[test]
+    fn dry_run_logs_since() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-name"),
+            &["--dry", "logs", "--since", "10m"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("--since 10m"), "Output should contain '--since' with its value");
+        assert!(stdout.contains("test-container"), "Output should contain the container name");
+    }
+
+    #[test]
+    fn dry_run_lifecycle_commands_target_named_container() {
+        for (subcommand, docker_command) in [("stop", "stop"), ("restart", "restart"), ("attach", "attach")] {
+            let (stdout, _, success) = run_dry(
+                Path::new("tests/fixtures/with-name"),
+                &["--dry", subcommand],
+            );
+
+            assert!(success, "'{}' should succeed in dry run", subcommand);
+            assert!(stdout.contains(&format!("docker {}", docker_command)),
+                "Output should contain 'docker {}'. Got: {}", docker_command, stdout);
+            assert!(stdout.contains("test-container"), "Output should contain the container name");
+            assert!(!stdout.contains("docker rm"), "'{}' should not remove the container", subcommand);
+        }
+    }
+
+    #[test]
+    fn lifecycle_commands_require_name() {
+        for subcommand in ["logs", "stop", "restart", "attach"] {
+            let (_, stderr, success) = run_dry(
+                Path::new("tests/fixtures/basic"),
+                &["--dry", subcommand],
+            );
+
+            assert!(!success, "'{}' should fail without a named container", subcommand);
+            assert!(stderr.contains(&format!("The '{}' command requires a named container", subcommand)),
+                "Error should mention the missing name. Got: {}", stderr);
+        }
+    }
+
+    #[test]
+    fn dry_run_shims_lists_every_configured_command() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-commands"),
+            &["--dry", "shims"],
+        );
+
+        assert!(success);
+        for command in ["npm", "node", "cargo"] {
+            assert!(stdout.contains(&format!(".contain/bin/{}", command)), "Output should contain a shim for '{}'. Got: {}", command, stdout);
+        }
+        assert!(!Path::new("tests/fixtures/with-commands/.contain").exists(), "Dry run should not write shims");
+    }
+
+    #[test]
+    fn shims_skip_any_matcher() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/basic"),
+            &["--dry", "shims", "install"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("No commands to shim"), "Output should explain that 'any' cannot be shimmed. Got: {}", stdout);
+    }
+
+    #[test]
+    fn shims_skip_unsafe_command_names() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/with-unsafe-commands"),
+            &["--dry", "shims"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains(".contain/bin/safe-tool"), "Safe names should be shimmed. Got: {}", stdout);
+        for command in ["rm -rf x", "a;b", "$(id)"] {
+            assert!(stdout.contains(&format!("Skipping '{}'", command)), "'{}' should be skipped. Got: {}", command, stdout);
+        }
+    }
+
+    #[test]
+    fn global_shims_keep_other_projects_shims() {
+        let data = std::env::temp_dir().join(format!("contain-shims-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data);
+        let dir = data.join("contain/shims");
+        std::fs::create_dir_all(&dir).unwrap();
+        let other = dir.join("other-tool");
+        std::fs::write(&other, "#!/bin/sh\n# contain shim\nexec contain run other-tool \"$@\"\n").unwrap();
+
+        let (stdout, stderr, success) = run_dry_with_env(
+            Path::new("tests/fixtures/with-commands"),
+            &["shims", "--global"],
+            &[("XDG_DATA_HOME", data.to_str().unwrap())],
+        );
+
+        assert!(success, "Got: {} {}", stdout, stderr);
+        assert!(dir.join("npm").exists(), "The project's shims should be written");
+        assert!(other.exists(), "Shims of other projects should be kept");
+        let npm = std::fs::read_to_string(dir.join("npm")).unwrap();
+        assert!(npm.contains(" run 'npm' \"$@\""), "The command should be quoted. Got: {}", npm);
+
+        let _ = std::fs::remove_dir_all(&data);
+    }
+
+    #[test]
+    fn hook_finds_every_kind_of_configuration() {
+        let (hook, _, success) = run_dry(Path::new("tests/fixtures/basic"), &["hook", "bash"]);
+        assert!(success);
+
+        let root = std::env::temp_dir().join(format!("contain-hook-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let projects = [
+            ("toml", "contain.toml", "[[images]]\nimage = \"x\"\n"),
+            ("cargo", "Cargo.toml", "[package]\nname = \"x\"\n\n[[package.metadata.contain.images]]\nimage = \"x\"\n"),
+            ("npm", "package.json", "{\n  \"contain\": {\"images\": []}\n}\n"),
+        ];
+        for (name, file, contents) in projects {
+            std::fs::create_dir_all(root.join(name).join(".contain/bin")).unwrap();
+            std::fs::create_dir_all(root.join(name).join("src")).unwrap();
+            std::fs::write(root.join(name).join(file), contents).unwrap();
+
+            let output = Command::new("bash")
+                .arg("-c")
+                .arg(format!("{}\ncd '{}' && _contain_hook && printf '%s' \"$PATH\"", hook, root.join(name).join("src").display()))
+                .output()
+                .expect("failed to run bash");
+            let path = String::from_utf8_lossy(&output.stdout);
+            assert!(path.starts_with(&format!("{}/.contain/bin:", root.join(name).display())),
+                "Shims of a project configured in {} should be activated. Got: {}", file, path);
+        }
+
+        // A manifest that does not embed a configuration is not a project root
+        std::fs::write(root.join("npm/src/package.json"), "{\"name\": \"x\"}\n").unwrap();
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(format!("{}\ncd '{}' && _contain_hook && printf '%s' \"$PATH\"", hook, root.join("npm/src").display()))
+            .output()
+            .expect("failed to run bash");
+        assert!(String::from_utf8_lossy(&output.stdout).starts_with(&format!("{}/.contain/bin:", root.join("npm").display())));
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn init_refuses_to_shadow_another_configuration() {
+        let dir = std::env::temp_dir().join(format!("contain-init-shadow-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("contain.toml"), "[[images]]\nimage = \"x\"\n").unwrap();
+
+        let (_, stderr, success) = run_dry(&dir, &["init", "--template", "go"]);
+        assert!(!success);
+        assert!(stderr.contains("contain.toml' already configures contain here"), "init should name the shadowed file. Got: {}", stderr);
+        assert!(!dir.join(".contain.yaml").exists());
+
+        let (_, stderr, success) = run_dry(&dir, &["init", "--template", "go", "--force"]);
+        assert!(success);
+        assert!(stderr.contains("would take precedence"), "init --force should still warn. Got: {}", stderr);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn hook_prints_shell_specific_activation() {
+        let (bash, _, success) = run_dry(Path::new("tests/fixtures/basic"), &["hook", "bash"]);
+        assert!(success);
+        assert!(bash.contains("PROMPT_COMMAND"), "bash hook should use PROMPT_COMMAND");
+
+        let (zsh, _, success) = run_dry(Path::new("tests/fixtures/basic"), &["hook", "zsh"]);
+        assert!(success);
+        assert!(zsh.contains("add-zsh-hook chpwd"), "zsh hook should use chpwd");
+
+        let (fish, _, success) = run_dry(Path::new("tests/fixtures/basic"), &["hook", "fish"]);
+        assert!(success);
+        assert!(fish.contains("--on-variable PWD"), "fish hook should watch PWD");
+    }
+
+    #[test]
+    fn completions_cover_subcommands_and_configured_commands() {
+        for shell in ["bash", "zsh", "fish"] {
+            let (stdout, _, success) = run_dry(Path::new("tests/fixtures/basic"), &["completions", shell]);
+
+            assert!(success, "completions for {} should succeed", shell);
+            assert!(stdout.contains("status"), "{} completions should list subcommands", shell);
+            assert!(stdout.contains("skip-ports"), "{} completions should list global flags", shell);
+            assert!(stdout.contains("contain complete-commands"), "{} completions should complete configured commands", shell);
+        }
+    }
+
+    #[test]
+    fn complete_commands_lists_routed_commands() {
+        let (stdout, _, success) = run_dry(Path::new("tests/fixtures/with-commands"), &["complete-commands"]);
+
+        assert!(success);
+        assert_eq!(stdout.lines().collect::<Vec<_>>(), vec!["npm", "node", "cargo"]);
+    }
+
+    #[test]
+    fn init_detects_project_type_and_refuses_to_overwrite() {
+        let dir = std::env::temp_dir().join(format!("contain-init-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Cargo.toml"), "[package]\n").unwrap();
+
+        let (stdout, _, success) = run_dry(&dir, &["init"]);
+        assert!(success, "init should succeed in an empty project");
+        assert!(stdout.contains("rust"), "init should detect a rust project. Got: {}", stdout);
+
+        let config = std::fs::read_to_string(dir.join(".contain.yaml")).unwrap();
+        assert!(config.contains("- cargo"), "config should route cargo");
+        assert!(config.contains("PATH=/cache/cargo/bin:/usr/local/cargo/bin:"), "config should keep the toolchain binaries on PATH");
+        assert!(config.contains("dockerfile: Dockerfile.contain"), "config should reference the generated Dockerfile");
+
+        let dockerfile = std::fs::read_to_string(dir.join("Dockerfile.contain")).unwrap();
+        assert!(dockerfile.contains("# contain start"), "Dockerfile should contain the user-creation block");
+        assert!(dockerfile.contains("ARG uid"), "Dockerfile should declare the uid build arg");
+
+        let (_, stderr, success) = run_dry(&dir, &["init"]);
+        assert!(!success, "init should refuse to overwrite existing files");
+        assert!(stderr.contains("already exists"), "Error should mention the existing file. Got: {}", stderr);
+
+        let (_, _, success) = run_dry(&dir, &["init", "--force", "--template", "go"]);
+        assert!(success, "init --force should overwrite existing files");
+        let config = std::fs::read_to_string(dir.join(".contain.yaml")).unwrap();
+        assert!(config.contains("- go"), "config should be regenerated from the go template");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn init_routes_gradle_commands() {
+        let dir = std::env::temp_dir().join(format!("contain-init-gradle-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("build.gradle.kts"), "").unwrap();
+
+        let (stdout, _, success) = run_dry(&dir, &["init"]);
+        assert!(success);
+        assert!(stdout.contains("gradle"), "init should detect a gradle project. Got: {}", stdout);
+
+        let config = std::fs::read_to_string(dir.join(".contain.yaml")).unwrap();
+        assert!(config.contains("- gradle"), "config should route gradle");
+        assert!(config.contains("- ./gradlew"), "config should route the gradle wrapper");
+        assert!(config.contains("GRADLE_USER_HOME=/cache/gradle"), "config should point gradle at the cache volume");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn dry_run_init_writes_nothing() {
+        let dir = std::env::temp_dir().join(format!("contain-init-dry-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let (stdout, _, success) = run_dry(&dir, &["--dry", "init", "--template", "python"]);
+        assert!(success);
+        assert!(stdout.contains("python:3-bookworm"), "Output should show the generated Dockerfile");
+        assert!(!dir.join(".contain.yaml").exists(), "Dry run should not write files");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn dry_run_combined_flags() {
+        let (stdout, _, success) = run_dry(
+            Path::new("tests/fixtures/basic"),
+            &["--dry", "-k", "run", "-i", "echo", "test"],
+        );
+
+        assert!(success);
+        assert!(stdout.contains("-it"), "Output should contain '-it' for interactive");
+        assert!(!stdout.contains("--rm"), "Output should NOT contain '--rm' when -k is used");
+    }
+}
+
+#[cfg(test)]
+mod fake_docker_tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A project with a named entry and a `docker` stand-in, in a directory of its own.
+    struct FakeDocker {
+        dir: PathBuf,
+    }
+
+    impl FakeDocker {
+        /// `test-container` does not exist until `set_container` is called.
+        fn new(test: &str) -> FakeDocker {
+            use std::os::unix::fs::PermissionsExt;
+
+            let dir = std::env::temp_dir().join(format!("contain-fake-docker-{}-{}", test, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(dir.join("project")).unwrap();
+            std::fs::create_dir_all(dir.join("bin")).unwrap();
+            std::fs::create_dir_all(dir.join("state")).unwrap();
+            std::fs::copy("tests/fixtures/with-name/.contain.yaml", dir.join("project/.contain.yaml")).unwrap();
+            std::fs::write(dir.join("project/Dockerfile"), "FROM alpine\n").unwrap();
+
+            let script = format!(r#"#!/bin/sh
+echo "$*" >> '{dir}/calls'
+case "$1 $2 $3" in
+  "container inspect test-container") cat '{dir}/container.json' 2>/dev/null || exit 1 ;;
+  "container inspect "*) exit 1 ;;
+  "ps -a "*) cat '{dir}/ps' 2>/dev/null || true ;;
+  "volume ls "*|"network ls "*) cat "{dir}/$1s" 2>/dev/null || true ;;
+  "image inspect "*) cat '{image}' ;;
+esac
+"#, dir = dir.display(), image = canonicalize("tests/fixtures/inspect/image.json").unwrap().display());
+            let docker = dir.join("bin/docker");
+            std::fs::write(&docker, script).unwrap();
+            std::fs::set_permissions(&docker, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+            FakeDocker { dir }
+        }
+
+        fn project(&self) -> PathBuf {
+            self.dir.join("project")
+        }
+
+        /// Makes `docker container inspect test-container` answer with this container.
+        fn set_container(&self, container: &serde_json::Value) {
+            std::fs::write(self.dir.join("container.json"), serde_json::json!([container]).to_string()).unwrap();
+        }
+
+        /// Sets `autostart: true` on the named entry.
+        fn enable_autostart(&self) {
+            let config = self.project().join(".contain.yaml");
+            let yaml = std::fs::read_to_string(&config).unwrap();
+            std::fs::write(&config, yaml.replace("    name: test-container\n", "    name: test-container\n    autostart: true\n")).unwrap();
+        }
+
+        /// Makes `docker ps -a` list these rows: name, image, status and the requested labels, tab separated.
+        fn set_ps(&self, rows: &[&str]) {
+            std::fs::write(self.dir.join("ps"), rows.join("\n") + "\n").unwrap();
+        }
+
+        /// Makes `docker volume ls` or `docker network ls` (`kind`) list these name and project
+        /// root pairs.
+        fn set_resources(&self, kind: &str, rows: &[(&str, &str)]) {
+            let lines: String = rows.iter().map(|(name, root)| format!("{}\t{}\n", name, root)).collect();
+            std::fs::write(self.dir.join(format!("{}s", kind)), lines).unwrap();
+        }
+
+        /// Runs contain in the project, returning stdout, stderr and whether it succeeded.
+        fn run(&self, args: &[&str]) -> (String, String, bool) {
+            let path = format!("{}:{}", self.dir.join("bin").display(), std::env::var("PATH").unwrap_or_default());
+            let output = Command::new(canonicalize("./target/debug/contain").unwrap())
+                .current_dir(self.project())
+                .env("CONTAIN_PASSTHROUGH", "0")
+                .env("CONTAIN_DOCKER_CLI", "1")
+                .env("PATH", path)
+                .env("XDG_STATE_HOME", self.dir.join("state"))
+                .args(args)
+                .output()
+                .expect("failed to execute contain");
+
+            (
+                String::from_utf8_lossy(&output.stdout).to_string(),
+                String::from_utf8_lossy(&output.stderr).to_string(),
+                output.status.success(),
+            )
+        }
+
+        /// The docker invocations made so far, one per line.
+        fn calls(&self) -> Vec<String> {
+            std::fs::read_to_string(self.dir.join("calls"))
+                .unwrap_or_default()
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+    }
+
+    impl Drop for FakeDocker {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// The captured `docker container inspect` output of a contain-managed `test-container`,
+    /// changed to be in `status` and to mount `source` at `destination`.
+    fn container_json(status: &str, source: &Path, destination: &str) -> serde_json::Value {
+        let captured = std::fs::read_to_string("tests/fixtures/inspect/container.json").unwrap();
+        let mut container = serde_json::from_str::<serde_json::Value>(&captured).unwrap()[0].take();
+        container["State"]["Status"] = status.into();
+        container["Config"]["Labels"]["contain.root-path"] = source.display().to_string().into();
+        container["Mounts"][0]["Source"] = source.display().to_string().into();
+        container["Mounts"][0]["Destination"] = destination.into();
+        container
+    }
+
+    #[test]
+    fn run_inspects_a_named_container_once() {
+        let fake = FakeDocker::new("inspect-once");
+        fake.set_container(&container_json("running", &fake.project(), "/workdir"));
+
+        let (stdout, stderr, success) = fake.run(&["run", "echo", "hello"]);
+
+        assert!(success, "run should succeed. Got: {} {}", stdout, stderr);
+        assert!(stdout.contains("executing inside existing container"), "The running container should be reused. Got: {}", stdout);
+        let inspects = fake.calls().iter().filter(|call| call.as_str() == "container inspect test-container").count();
+        assert_eq!(inspects, 1, "The container should be inspected once. Calls: {:?}", fake.calls());
+    }
+
+    #[test]
+    fn run_reports_a_different_workdir() {
+        let fake = FakeDocker::new("workdir-mismatch");
+        fake.set_container(&container_json("running", &fake.project(), "/workdir"));
+
+        let (_, stderr, success) = fake.run(&["--workdir", "/src", "run", "echo", "hello"]);
+
+        assert!(!success);
+        assert!(stderr.contains("mounts this project at '/workdir' rather than '/src'"), "A workdir override should be reported as such. Got: {}", stderr);
+    }
+
+    /// Whether docker was invoked with arguments starting with `prefix`.
+    fn called(calls: &[String], prefix: &str) -> bool {
+        calls.iter().any(|call| call.starts_with(prefix))
+    }
+
+    #[test]
+    fn run_unpauses_a_paused_container() {
+        let fake = FakeDocker::new("run-paused");
+        fake.set_container(&container_json("paused", &fake.project(), "/workdir"));
+
+        let (stdout, stderr, success) = fake.run(&["run", "echo", "hello"]);
+
+        assert!(success, "run should succeed. Got: {} {}", stdout, stderr);
+        let calls = fake.calls();
+        assert!(called(&calls, "unpause test-container"), "A paused container should be unpaused. Calls: {:?}", calls);
+        assert!(called(&calls, "exec "), "The command should run inside the unpaused container. Calls: {:?}", calls);
+    }
+
+    #[test]
+    fn run_reports_a_restarting_container() {
+        let fake = FakeDocker::new("run-restarting");
+        fake.set_container(&container_json("restarting", &fake.project(), "/workdir"));
+
+        let (_, stderr, success) = fake.run(&["run", "echo", "hello"]);
+
+        assert!(!success);
+        assert!(stderr.contains("is restarting"), "A restarting container should be reported. Got: {}", stderr);
+        assert!(!called(&fake.calls(), "exec "), "Nothing should run in a restarting container");
+    }
+
+    #[test]
+    fn run_uses_a_new_container_while_the_named_one_is_stopped() {
+        let fake = FakeDocker::new("run-exited");
+        fake.set_container(&container_json("exited", &fake.project(), "/workdir"));
+
+        let (stdout, stderr, success) = fake.run(&["run", "echo", "hello"]);
+
+        assert!(success, "run should succeed. Got: {} {}", stdout, stderr);
+        let calls = fake.calls();
+        let run = calls.iter().find(|call| call.starts_with("run ")).expect("A new container should be run");
+        assert!(!run.contains("--name test-container"), "The new container should not take the stopped one's name. Got: {}", run);
+        assert!(!called(&calls, "start test-container"), "The stopped container should be left alone. Calls: {:?}", calls);
+    }
+
+    #[test]
+    fn run_autostart_recreates_a_dead_container() {
+        let fake = FakeDocker::new("autostart-dead");
+        fake.enable_autostart();
+        fake.set_container(&container_json("dead", &fake.project(), "/workdir"));
+
+        let (stdout, stderr, success) = fake.run(&["run", "echo", "hello"]);
+
+        assert!(success, "run should succeed. Got: {} {}", stdout, stderr);
+        let calls = fake.calls();
+        let removed = calls.iter().position(|call| call == "rm -f test-container");
+        let created = calls.iter().position(|call| call.starts_with("run ") && call.contains("--name test-container"));
+        let executed = calls.iter().position(|call| call.starts_with("exec "));
+        assert!(removed.is_some() && removed < created && created < executed,
+            "A dead container should be recreated before the command runs in it. Calls: {:?}", calls);
+    }
+
+    #[test]
+    fn up_unpauses_a_paused_container() {
+        let fake = FakeDocker::new("up-paused");
+        fake.set_container(&container_json("paused", &fake.project(), "/workdir"));
+
+        let (stdout, stderr, success) = fake.run(&["up"]);
+
+        assert!(success, "up should succeed. Got: {} {}", stdout, stderr);
+        let calls = fake.calls();
+        assert!(called(&calls, "unpause test-container"), "A paused container should be unpaused. Calls: {:?}", calls);
+        assert!(!called(&calls, "run "), "No new container should be created. Calls: {:?}", calls);
+    }
+
+    #[test]
+    fn up_recreates_a_dead_container() {
+        let fake = FakeDocker::new("up-dead");
+        fake.set_container(&container_json("dead", &fake.project(), "/workdir"));
+
+        let (stdout, stderr, success) = fake.run(&["up"]);
+
+        assert!(success, "up should succeed. Got: {} {}", stdout, stderr);
+        let calls = fake.calls();
+        let removed = calls.iter().position(|call| call == "rm -f test-container");
+        let created = calls.iter().position(|call| call.starts_with("run ") && call.contains("--name test-container"));
+        assert!(removed.is_some() && created.is_some() && removed < created,
+            "A dead container should be removed and created again. Calls: {:?}", calls);
+    }
+
+    #[test]
+    fn up_reports_a_restarting_container() {
+        let fake = FakeDocker::new("up-restarting");
+        fake.set_container(&container_json("restarting", &fake.project(), "/workdir"));
+
+        let (_, stderr, success) = fake.run(&["up"]);
+
+        assert!(!success);
+        assert!(stderr.contains("is restarting"), "A restarting container should be reported. Got: {}", stderr);
+        let calls = fake.calls();
+        assert!(!called(&calls, "rm ") && !called(&calls, "run "), "A restarting container should be left alone. Calls: {:?}", calls);
+    }
+
+    #[test]
+    fn down_removes_a_paused_container_by_force() {
+        let fake = FakeDocker::new("down-paused");
+        fake.set_container(&container_json("paused", &fake.project(), "/workdir"));
+
+        let (stdout, stderr, success) = fake.run(&["down"]);
+
+        assert!(success, "down should succeed. Got: {} {}", stdout, stderr);
+        let calls = fake.calls();
+        assert!(called(&calls, "rm -f test-container"), "A paused container should be removed by force. Calls: {:?}", calls);
+        assert!(!called(&calls, "stop "), "A paused container cannot be stopped gracefully. Calls: {:?}", calls);
+    }
+
+    #[test]
+    fn stop_leaves_an_exited_container_alone() {
+        let fake = FakeDocker::new("stop-exited");
+        fake.set_container(&container_json("exited", &fake.project(), "/workdir"));
+
+        let (stdout, stderr, success) = fake.run(&["stop"]);
+
+        assert!(success, "stop should succeed. Got: {} {}", stdout, stderr);
+        assert!(stdout.contains("is not running (exited)"), "An exited container should be reported as such. Got: {}", stdout);
+        assert!(!called(&fake.calls(), "stop "), "Nothing should be stopped. Calls: {:?}", fake.calls());
+    }
+
+    /// Exited containers of the fake project: kept ones with and without a creation time, a
+    /// background one, and one whose project directory is gone.
+    fn exited_containers(fake: &FakeDocker) {
+        let project = fake.project();
+        fake.set_ps(&[
+            &format!("kept-old\ttest-image:latest\tExited (0) 2 years ago\t{}\t\t1000", project.display()),
+            &format!("kept-unlabelled\ttest-image:latest\tExited (0) 2 years ago\t{}\t\t", project.display()),
+            &format!("background\ttest-image:latest\tExited (0) 2 years ago\t{}\tabc123\t1000", project.display()),
+            "orphaned\ttest-image:latest\tExited (0) 2 years ago\t/nonexistent/contain-project\tabc123\t1000",
+        ]);
+    }
+
+    #[test]
+    fn gc_keeps_kept_containers_by_default() {
+        let fake = FakeDocker::new("gc-default");
+        exited_containers(&fake);
+
+        let (stdout, stderr, success) = fake.run(&["gc"]);
+
+        assert!(success, "gc should succeed. Got: {} {}", stdout, stderr);
+        let calls = fake.calls();
+        assert!(calls.contains(&"rm orphaned".to_string()), "Containers of a removed project should be removed. Calls: {:?}", calls);
+        assert!(!calls.contains(&"rm kept-old".to_string()), "Kept containers should stay without --older-than. Calls: {:?}", calls);
+        assert!(!calls.contains(&"rm kept-unlabelled".to_string()), "Kept containers should stay without --older-than. Calls: {:?}", calls);
+    }
+
+    #[test]
+    fn gc_removes_old_kept_containers_with_older_than() {
+        let fake = FakeDocker::new("gc-older-than");
+        exited_containers(&fake);
+
+        let (stdout, stderr, success) = fake.run(&["gc", "--older-than", "1d"]);
+
+        assert!(success, "gc should succeed. Got: {} {}", stdout, stderr);
+        let calls = fake.calls();
+        assert!(calls.contains(&"rm kept-old".to_string()), "Old kept containers should be removed. Calls: {:?}", calls);
+        assert!(!calls.contains(&"rm kept-unlabelled".to_string()), "Containers without a creation time should stay. Calls: {:?}", calls);
+        assert!(!calls.contains(&"rm background".to_string()), "Background containers should stay. Calls: {:?}", calls);
+    }
+
+    #[test]
+    fn gc_removes_orphaned_volumes_and_networks() {
+        let fake = FakeDocker::new("gc-resources");
+        let project = fake.project().display().to_string();
+        fake.set_resources("volume", &[("orphaned-volume", "/nonexistent/contain-project"), ("live-volume", &project)]);
+        fake.set_resources("network", &[("orphaned-network", "/nonexistent/contain-project"), ("live-network", &project)]);
+
+        let (stdout, stderr, success) = fake.run(&["gc"]);
+
+        assert!(success, "gc should succeed. Got: {} {}", stdout, stderr);
+        let calls = fake.calls();
+        assert!(calls.contains(&"volume rm orphaned-volume".to_string()), "Orphaned volumes should be removed. Calls: {:?}", calls);
+        assert!(calls.contains(&"network rm orphaned-network".to_string()), "Orphaned networks should be removed. Calls: {:?}", calls);
+        assert!(!calls.iter().any(|call| call.ends_with("rm live-volume") || call.ends_with("rm live-network")),
+            "Resources of an existing project should stay. Calls: {:?}", calls);
+    }
+
+    #[test]
+    fn status_reads_null_mounts() {
+        let fake = FakeDocker::new("null-mounts");
+        let mut container = container_json("running", &fake.project(), "/workdir");
+        container["Mounts"] = serde_json::Value::Null;
+        fake.set_container(&container);
+
+        let (stdout, stderr, success) = fake.run(&["status"]);
+
+        assert!(success, "status should succeed. Got: {} {}", stdout, stderr);
+        assert!(stdout.contains("running since 2024-05-01 10:00:00"), "The container should be read. Got: {}", stdout);
+    }
+
+    #[test]
+    fn status_reads_null_labels_as_unmanaged() {
+        let fake = FakeDocker::new("null-labels");
+        let mut container = container_json("running", &fake.project(), "/workdir");
+        container["Config"]["Labels"] = serde_json::Value::Null;
+        fake.set_container(&container);
+
+        let (stdout, stderr, success) = fake.run(&["status"]);
+
+        assert!(success, "status should succeed. Got: {} {}", stdout, stderr);
+        assert!(stdout.contains("Not created"), "A container without labels is not contain's. Got: {}", stdout);
+    }
+
+    #[test]
+    fn status_reads_removing_as_dead() {
+        let fake = FakeDocker::new("removing");
+        fake.set_container(&container_json("removing", &fake.project(), "/workdir"));
+
+        let (stdout, stderr, success) = fake.run(&["status"]);
+
+        assert!(success, "status should succeed. Got: {} {}", stdout, stderr);
+        assert!(stdout.contains("dead"), "A container being removed should be shown as dead. Got: {}", stdout);
+    }
+
+    #[test]
+    fn unknown_state_is_reported_and_removable() {
+        let fake = FakeDocker::new("unknown-state");
+        fake.set_container(&container_json("hibernating", &fake.project(), "/workdir"));
+
+        let (stdout, stderr, success) = fake.run(&["status"]);
+        assert!(success, "status should succeed. Got: {} {}", stdout, stderr);
+        assert!(stdout.contains("unknown"), "An unknown state should be shown as such. Got: {}", stdout);
+
+        let (_, stderr, success) = fake.run(&["up"]);
+        assert!(!success);
+        assert!(stderr.contains("is in a state contain does not know"), "up should refuse an unknown state. Got: {}", stderr);
+
+        let (stdout, stderr, success) = fake.run(&["down"]);
+        assert!(success, "down should succeed. Got: {} {}", stdout, stderr);
+        assert!(called(&fake.calls(), "rm -f test-container"), "down should remove the container by force. Calls: {:?}", fake.calls());
+    }
+
+    #[test]
+    fn up_reads_an_image_with_null_labels() {
+        let fake = FakeDocker::new("image-null-labels");
+
+        let (stdout, stderr, success) = fake.run(&["up"]);
+
+        assert!(success, "up should succeed. Got: {} {}", stdout, stderr);
+        assert!(called(&fake.calls(), "image inspect "), "The image should be inspected. Calls: {:?}", fake.calls());
+        assert!(!stderr.contains("Unexpected output"), "The captured image should be read. Got: {}", stderr);
+    }
+}
+
+#[cfg(test)]
+mod engine_api_tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    /// A fake daemon answering Engine API requests on a Unix socket, and a `docker` stand-in
+    /// for what contain still runs through the CLI. The container `test-container` of a copy
+    /// of the with-name fixture exists and is running, and so does its image. Containers it
+    /// creates get the id `c0ffee` and exit with 5, commands executed in it exit with 3.
+    struct FakeEngine {
+        dir: PathBuf,
+        requests: Arc<Mutex<Vec<(String, String)>>>,
+    }
+
+    impl FakeEngine {
+        fn new(test: &str) -> FakeEngine {
+            use std::io::{Read, Write};
+            use std::os::unix::fs::PermissionsExt;
+            use std::os::unix::net::UnixListener;
+
+            let dir = std::env::temp_dir().join(format!("contain-engine-{}-{}", test, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(dir.join("contain")).unwrap();
+            std::fs::create_dir_all(dir.join("bin")).unwrap();
+            std::fs::create_dir_all(dir.join("project")).unwrap();
+            std::fs::copy("tests/fixtures/with-name/.contain.yaml", dir.join("project/.contain.yaml")).unwrap();
+            std::fs::write(dir.join("project/Dockerfile"), "FROM alpine\n").unwrap();
+            // Keep the idle reaper from talking to the fake daemon
+            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+            std::fs::write(dir.join("contain/last-reap"), now.to_string()).unwrap();
+
+            let docker = dir.join("bin/docker");
+            std::fs::write(&docker, format!("#!/bin/sh\necho \"$*\" >> '{}/calls'\n", dir.display())).unwrap();
+            std::fs::set_permissions(&docker, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+            let root = dir.join("project");
+            let container = format!(r#"{{"Id":"0123456789abcdef","Name":"/test-container","Created":"2024-05-01T09:59:58.5Z",
+                "State":{{"Status":"running","ExitCode":0,"StartedAt":"2024-05-01T10:00:00.123Z","FinishedAt":"0001-01-01T00:00:00Z",
+                    "Health":{{"Status":"healthy","FailingStreak":0}}}},
+                "Config":{{"Image":"test-image:latest","Labels":{{"contain.managed":"true","contain.root-path":"{}"}}}},
+                "Mounts":[{{"Type":"bind","Source":"{}","Destination":"/workdir"}}],
+                "NetworkSettings":{{"Ports":{{"80/tcp":[{{"HostIp":"0.0.0.0","HostPort":"8080"}}],"443/tcp":null}}}}}}"#,
+                root.display(), root.display());
+            let containers = format!(r#"[{{"Id":"0123456789abcdef","Names":["/test-container"],"Image":"test-image:latest",
+                "State":"running","Status":"Up 2 hours","Labels":{{"contain.managed":"true","contain.root-path":"{}"}}}}]"#,
+                root.display());
+            let images: serde_json::Value = serde_json::from_str(&std::fs::read_to_string("tests/fixtures/inspect/image.json").unwrap()).unwrap();
+            let image = images[0].to_string();
+
+            let listener = UnixListener::bind(dir.join("docker.sock")).unwrap();
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let received = Arc::clone(&requests);
+
+            std::thread::spawn(move || {
+                for mut stream in listener.incoming().flatten() {
+                    let (container, containers, image) = (container.clone(), containers.clone(), image.clone());
+                    let received = Arc::clone(&received);
+
+                    // Attached streams and waits stay open while other requests are made
+                    std::thread::spawn(move || {
+                        let mut request = Vec::new();
+                        let mut buffer = [0; 1024];
+                        let head_end = loop {
+                            if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                                break end + 4;
+                            }
+                            match stream.read(&mut buffer) {
+                                Ok(0) | Err(_) => return,
+                                Ok(n) => request.extend_from_slice(&buffer[..n]),
+                            }
+                        };
+
+                        let head = String::from_utf8_lossy(&request[..head_end]).to_string();
+                        let length: usize = head.lines()
+                            .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|l| l.trim().parse().unwrap_or(0)))
+                            .unwrap_or(0);
+                        while request.len() < head_end + length {
+                            match stream.read(&mut buffer) {
+                                Ok(0) | Err(_) => break,
+                                Ok(n) => request.extend_from_slice(&buffer[..n]),
+                            }
+                        }
+
+                        let line = head.lines().next().unwrap_or_default().to_string();
+                        let body = String::from_utf8_lossy(&request[head_end..]).to_string();
+                        received.lock().unwrap().push((line.clone(), body));
+
+                        let json = |status: &str, body: &str| format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                            status, body.len(), body);
+                        let upgraded = "HTTP/1.1 101 UPGRADED\r\nContent-Type: application/vnd.docker.raw-stream\r\nConnection: Upgrade\r\nUpgrade: tcp\r\n\r\n";
+                        let frame = |stream: u8, output: &str| {
+                            let mut frame = vec![stream, 0, 0, 0];
+                            frame.extend_from_slice(&(output.len() as u32).to_be_bytes());
+                            frame.extend_from_slice(output.as_bytes());
+                            frame
+                        };
+
+                        let response: Vec<u8> = if line.starts_with("GET /containers/test-container/json ") {
+                            // Sent chunked, as the daemon does for larger responses
+                            let (first, second) = container.split_at(container.len() / 2);
+                            format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                                first.len(), first, second.len(), second).into_bytes()
+                        } else if line.starts_with("GET /containers/json?") {
+                            json("200 OK", &containers).into_bytes()
+                        } else if line.starts_with("GET /images/json?") || line.starts_with("GET /networks?") {
+                            json("200 OK", "[]").into_bytes()
+                        } else if line.starts_with("GET /volumes?") {
+                            json("200 OK", r#"{"Volumes":null,"Warnings":null}"#).into_bytes()
+                        } else if line.starts_with("GET /images/test-image:latest/json ") {
+                            json("200 OK", &image).into_bytes()
+                        } else if line.starts_with("POST /containers/create?") {
+                            json("201 Created", r#"{"Id":"c0ffee","Warnings":[]}"#).into_bytes()
+                        } else if line.starts_with("POST /containers/c0ffee/start ") {
+                            b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()
+                        } else if line.starts_with("POST /containers/c0ffee/attach?") {
+                            let mut response = upgraded.as_bytes().to_vec();
+                            response.extend(frame(1, "out\n"));
+                            response.extend(frame(2, "err\n"));
+                            response
+                        } else if line.starts_with("POST /containers/c0ffee/wait?") {
+                            json("200 OK", r#"{"StatusCode":5}"#).into_bytes()
+                        } else if line.starts_with("DELETE /containers/test-container?force=true ") {
+                            b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()
+                        } else if line.starts_with("POST /containers/test-container/exec ") {
+                            json("201 Created", r#"{"Id":"e1"}"#).into_bytes()
+                        } else if line.starts_with("POST /exec/e1/start ") {
+                            format!("{}hello from exec\r\n", upgraded).into_bytes()
+                        } else if line.starts_with("GET /exec/e1/json ") {
+                            json("200 OK", r#"{"Running":false,"ExitCode":3}"#).into_bytes()
+                        } else {
+                            json("404 Not Found", r#"{"message":"No such object"}"#).into_bytes()
+                        };
+                        let _ = stream.write_all(&response);
+                    });
+                }
+            });
+
+            FakeEngine { dir, requests }
+        }
+
+        /// Runs contain in the project, returning stdout, stderr and the exit code.
+        fn run(&self, args: &[&str]) -> (String, String, i32) {
+            let path = format!("{}:{}", self.dir.join("bin").display(), std::env::var("PATH").unwrap_or_default());
+            let output = Command::new(canonicalize("./target/debug/contain").unwrap())
+                .current_dir(self.dir.join("project"))
+                .env("CONTAIN_PASSTHROUGH", "0")
+                .env("DOCKER_HOST", format!("unix://{}", self.dir.join("docker.sock").display()))
+                .env("XDG_STATE_HOME", &self.dir)
+                .env("PATH", path)
+                .args(args)
+                .output()
+                .expect("failed to execute contain");
+
+            (
+                String::from_utf8_lossy(&output.stdout).to_string(),
+                String::from_utf8_lossy(&output.stderr).to_string(),
+                output.status.code().unwrap_or(-1),
+            )
+        }
+
+        fn project(&self) -> PathBuf {
+            self.dir.join("project")
+        }
+
+        /// The request lines the fake daemon received, in order.
+        fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap().iter().map(|(line, _)| line.clone()).collect()
+        }
+
+        /// The JSON body of the first request starting with `prefix`.
+        fn body(&self, prefix: &str) -> serde_json::Value {
+            let requests = self.requests.lock().unwrap();
+            let (_, body) = requests.iter()
+                .find(|(line, _)| line.starts_with(prefix))
+                .unwrap_or_else(|| panic!("No request starting with '{}' in {:?}", prefix, requests));
+            serde_json::from_str(body).unwrap()
+        }
+
+        /// The position of the first request starting with `prefix`, failing if there is none.
+        fn position(&self, prefix: &str) -> usize {
+            self.requests().iter()
+                .position(|line| line.starts_with(prefix))
+                .unwrap_or_else(|| panic!("No request starting with '{}' in {:?}", prefix, self.requests()))
+        }
+
+        /// The docker CLI invocations made, one per line.
+        fn calls(&self) -> Vec<String> {
+            std::fs::read_to_string(self.dir.join("calls"))
+                .unwrap_or_default()
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+    }
+
+    impl Drop for FakeEngine {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn status_reads_state_from_engine_api() {
+        let engine = FakeEngine::new("status");
+
+        let (stdout, stderr, code) = engine.run(&["status"]);
+
+        assert_eq!(code, 0, "status should succeed. Got: {} {}", stdout, stderr);
+        assert!(stdout.contains("running since 2024-05-01 10:00:00 UTC"), "Status should come from the Engine API. Got: {}", stdout);
+        assert!(stdout.contains("healthy"), "The health check status should be shown");
+        assert!(stdout.contains("0.0.0.0:8080->80/tcp"), "Published ports should be shown");
+        assert!(stdout.contains("0123456789ab"), "The container id should be shown");
+        assert!(engine.calls().is_empty(), "The docker CLI should not be run. Calls: {:?}", engine.calls());
+    }
+
+    #[test]
+    fn ps_lists_containers_from_engine_api() {
+        let engine = FakeEngine::new("ps");
+
+        let (stdout, stderr, code) = engine.run(&["ps", "--project"]);
+
+        assert_eq!(code, 0, "ps should succeed. Got: {} {}", stdout, stderr);
+        assert!(stdout.contains("test-container"), "The container should be listed. Got: {}", stdout);
+        assert!(stdout.contains("Up 2 hours"), "The container status should be shown. Got: {}", stdout);
+
+        let root = engine.project();
+        let list = engine.requests().into_iter()
+            .find(|request| request.starts_with("GET /containers/json?all=true&filters="))
+            .expect("Containers should be listed through the Engine API");
+        assert!(list.contains("contain.managed%3Dtrue"), "Only managed containers should be listed. Got: {}", list);
+        assert!(list.contains(&format!("contain.root-path%3D{}", root.display())), "The project filter should be passed on. Got: {}", list);
+        engine.position("GET /images/json?filters=");
+        engine.position("GET /volumes?filters=");
+        assert!(engine.calls().is_empty(), "The docker CLI should not be run. Calls: {:?}", engine.calls());
+    }
+
+    #[test]
+    fn up_creates_and_starts_container_through_engine_api() {
+        let engine = FakeEngine::new("up");
+
+        let (stdout, stderr, code) = engine.run(&["up", "--recreate"]);
+
+        assert_eq!(code, 0, "up should succeed. Got: {} {}", stdout, stderr);
+        assert!(stdout.contains("is now running in the background"), "The container should be started. Got: {}", stdout);
+
+        let removed = engine.position("DELETE /containers/test-container?force=true ");
+        let created = engine.position("POST /containers/create?name=test-container ");
+        let started = engine.position("POST /containers/c0ffee/start ");
+        assert!(removed < created && created < started, "The container should be removed, created and started in order. Requests: {:?}", engine.requests());
+
+        let body = engine.body("POST /containers/create?");
+        assert_eq!(body["Image"], "test-image:latest");
+        assert_eq!(body["Cmd"], serde_json::json!(["sleep", "infinity"]), "The idle command should keep the container running");
+        assert_eq!(body["WorkingDir"], "/workdir");
+        assert_eq!(body["Labels"]["contain.managed"], "true");
+        assert!(body["Labels"]["contain.config-hash"].is_string(), "The configuration hash should be recorded. Got: {}", body);
+        assert_eq!(body["HostConfig"]["Mounts"][0], serde_json::json!({
+            "Type": "bind", "Source": engine.project().display().to_string(), "Target": "/workdir", "ReadOnly": false,
+        }));
+        assert_eq!(body["AttachStdout"], false, "A background container should not be attached to");
+        assert!(engine.calls().is_empty(), "The docker CLI should not be run. Calls: {:?}", engine.calls());
+    }
+
+    #[test]
+    fn run_creates_attaches_and_starts_container_through_engine_api() {
+        let engine = FakeEngine::new("run");
+        // Without a name there is no background container to execute in
+        std::fs::write(engine.project().join(".contain.yaml"),
+            "images:\n  - image: \"test-image:latest\"\n    dockerfile: Dockerfile\n    commands: any\n").unwrap();
+
+        let (stdout, stderr, code) = engine.run(&["run", "cargo", "test"]);
+
+        assert_eq!(code, 5, "The exit code of the container should be passed on. Got: {} {}", stdout, stderr);
+        assert!(stdout.ends_with("out\n"), "The container's stdout should be written to stdout. Got: {}", stdout);
+        assert_eq!(stderr, "err\n", "The container's stderr should be written to stderr");
+
+        let created = engine.position("POST /containers/create?");
+        let attached = engine.position("POST /containers/c0ffee/attach?stream=1&stdin=false&stdout=1&stderr=1 ");
+        let waiting = engine.position("POST /containers/c0ffee/wait?condition=removed ");
+        let started = engine.position("POST /containers/c0ffee/start ");
+        assert!(created < attached && attached < started && waiting < started,
+            "The container should be attached to and waited for before it starts. Requests: {:?}", engine.requests());
+
+        let body = engine.body("POST /containers/create?");
+        assert_eq!(body["Cmd"], serde_json::json!(["cargo", "test"]));
+        assert_eq!(body["HostConfig"]["AutoRemove"], true, "The container should be removed when it exits");
+        assert_eq!(body["Tty"], false);
+        assert_eq!(body["Labels"]["contain.root-path"], engine.project().display().to_string());
+        assert!(engine.calls().is_empty(), "The docker CLI should not be run. Calls: {:?}", engine.calls());
+    }
+
+    #[test]
+    fn run_executes_in_running_container_through_engine_api() {
+        let engine = FakeEngine::new("exec");
+
+        let (stdout, stderr, code) = engine.run(&["run", "cargo", "test"]);
+
+        assert_eq!(code, 3, "The exit code of the command should be passed on. Got: {} {}", stdout, stderr);
+        assert!(stdout.contains("executing inside existing container"), "The running container should be reused. Got: {}", stdout);
+        assert!(stdout.ends_with("hello from exec\r\n"), "The command's output should be written to stdout. Got: {}", stdout);
+
+        let created = engine.position("POST /containers/test-container/exec ");
+        let started = engine.position("POST /exec/e1/start ");
+        let inspected = engine.position("GET /exec/e1/json ");
+        assert!(created < started && started < inspected, "The command should be created, started and inspected in order. Requests: {:?}", engine.requests());
+
+        let body = engine.body("POST /containers/test-container/exec ");
+        assert_eq!(body["Cmd"], serde_json::json!(["cargo", "test"]));
+        assert_eq!(body["WorkingDir"], "/workdir/");
+        assert_eq!(body["Tty"], true);
+        assert_eq!(engine.body("POST /exec/e1/start ")["Detach"], false);
+        assert!(!engine.requests().iter().any(|line| line.starts_with("POST /containers/create")), "No container should be created");
+        assert!(engine.calls().is_empty(), "The docker CLI should not be run. Calls: {:?}", engine.calls());
+    }
+}